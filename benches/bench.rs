@@ -114,6 +114,32 @@ mod features {
     }
 }
 
+mod blocks {
+    use criterion::Criterion;
+    use std::path::PathBuf;
+    use ndarray::Array2;
+
+    use farthest_sampler::VoronoiDecomposer;
+
+    fn load_soap() -> Array2<f64> {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("soap.npy");
+        return ndarray_npy::read_npy(path).unwrap();
+    }
+
+    pub fn voronoi_soap(c: &mut Criterion) {
+        let data = load_soap();
+        for &block_size in &[1, 64, 256, 1024] {
+            let name = format!("Voronoi FPS/200 samples/SOAP/block size {}", block_size);
+            c.bench_function(&name, |b| b.iter(|| {
+                let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+                voronoi.set_block_size(block_size);
+                voronoi.select(199);
+            }));
+        }
+    }
+}
+
 criterion_group!(
     samples,
     samples::standard_boston, samples::standard_soap, samples::voronoi_boston, samples::voronoi_soap
@@ -124,4 +150,6 @@ criterion_group!(
     features::standard_boston, features::standard_soap, features::voronoi_boston, features::voronoi_soap
 );
 
-criterion_main!(samples, features);
+criterion_group!(blocks, blocks::voronoi_soap);
+
+criterion_main!(samples, features, blocks);
@@ -0,0 +1,69 @@
+#![allow(clippy::needless_return)]
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ndarray::Array2;
+
+use farthest_sampler::VoronoiDecomposer;
+
+const N_POINTS: usize = 50_000;
+const N_BLOBS: usize = 32;
+
+/// Small linear congruential generator, to get reproducible datasets without
+/// additional dependencies
+struct Lcg(u64);
+
+impl Lcg {
+    fn uniform(&mut self) -> f64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        return ((self.0 >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+    }
+
+    /// Sample a normal distribution with the Box-Muller transform
+    fn normal(&mut self) -> f64 {
+        let (u, v) = (self.uniform(), self.uniform());
+        return (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos();
+    }
+}
+
+/// Generate `N_POINTS` points in `n_features` dimensions, distributed in
+/// Gaussian blobs around random centers
+fn gaussian_blobs(n_features: usize) -> Array2<f64> {
+    let mut rng = Lcg(n_features as u64);
+    let centers = Array2::from_shape_simple_fn((N_BLOBS, n_features), || 10.0 * rng.uniform());
+
+    let mut points = Array2::zeros((N_POINTS, n_features));
+    for (i, mut point) in points.rows_mut().into_iter().enumerate() {
+        let center = centers.row(i % N_BLOBS);
+        for (x, &c) in point.iter_mut().zip(center) {
+            *x = c + rng.normal();
+        }
+    }
+
+    return points;
+}
+
+fn selection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Gaussian blobs");
+    group.sample_size(10);
+
+    for &n_features in &[16, 128] {
+        let points = gaussian_blobs(n_features);
+        for &n_select in &[100, 1000] {
+            let parameter = format!("{} features/{} samples", n_features, n_select);
+
+            group.bench_with_input(BenchmarkId::new("Voronoi FPS", &parameter), &n_select, |b, &n_select| b.iter(|| {
+                let mut voronoi = VoronoiDecomposer::with_capacity(points.view().into(), 0, n_select);
+                voronoi.select(n_select - 1);
+            }));
+
+            group.bench_with_input(BenchmarkId::new("Standard FPS", &parameter), &n_select, |b, &n_select| b.iter(|| {
+                farthest_sampler::simple::select_fps(points.view(), n_select, 0);
+            }));
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, selection);
+criterion_main!(benches);
@@ -0,0 +1,63 @@
+#ifndef FARTHEST_SAMPLER_H
+#define FARTHEST_SAMPLER_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque Voronoi decomposer */
+typedef struct vfps_decomposer vfps_decomposer;
+
+/* Create a new decomposer for the `n_rows x n_cols` points in `points`,
+ * starting from the point at index `initial`. The points must be stored in
+ * row-major (C) order, i.e. `points[i * n_cols + j]` is the feature `j` of
+ * the point `i`. From Fortran, this corresponds to a `(n_cols, n_rows)`
+ * array.
+ *
+ * The points are not copied: the caller must keep them alive and unchanged
+ * until `vfps_free` is called.
+ *
+ * Returns NULL on error: if `points` is NULL, if `n_rows` is zero, if
+ * `n_rows * n_cols` overflows, if `initial` is not smaller than `n_rows`, or
+ * if the distance between the initial point and another point is NaN (for
+ * example because the points contain NaN) or infinite.
+ *
+ * None of the functions in this header let a Rust panic unwind into the
+ * caller: an internal panic is reported in the same way as other errors. */
+vfps_decomposer* vfps_new(const double* points, size_t n_rows, size_t n_cols, size_t initial);
+
+/* Add the point at index `point` to the selected points. Returns 0 on
+ * success, -1 on error: if `decomposer` is NULL, if `point` is out of
+ * bounds, if the point is already selected, or if the new distances are NaN
+ * or infinite. */
+int vfps_add_point(vfps_decomposer* decomposer, size_t point);
+
+/* Select up to `n` new points with Farthest Point Sampling. The indexes of
+ * the new points are returned in a buffer owned by the caller, which must be
+ * released with `vfps_free_indices`. The number of selected points is
+ * written to `n_selected`.
+ *
+ * Returns NULL on error. */
+size_t* vfps_select(vfps_decomposer* decomposer, size_t n, size_t* n_selected);
+
+/* Release a buffer returned by `vfps_select` */
+void vfps_free_indices(size_t* indices, size_t n_selected);
+
+/* Get the squared radius of each Voronoi cell, in selection order. The number
+ * of cells is written to `n_cells`. The returned buffer is owned by the
+ * decomposer, and is only valid until the next call to `vfps_add_point`,
+ * `vfps_select` or `vfps_free`.
+ *
+ * Returns NULL on error. */
+const double* vfps_radii(const vfps_decomposer* decomposer, size_t* n_cells);
+
+/* Release a decomposer created with `vfps_new` */
+void vfps_free(vfps_decomposer* decomposer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
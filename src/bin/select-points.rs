@@ -0,0 +1,611 @@
+use std::io::IsTerminal;
+use std::path::Path;
+
+use clap::{App, Arg, value_t};
+use indicatif::{ProgressBar, ProgressStyle};
+use ndarray::{Array1, ArrayView2};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use farthest_sampler::VoronoiDecomposer;
+use farthest_sampler::io::{IndexType, NpyStreamWriter, SelectionMetadata};
+
+/// Where to write the radius that triggered the selection of each point
+enum RadiusOutput {
+    Discard,
+    /// Keep the radius in memory, and write them all at the end
+    Memory {
+        path: String,
+        radius: Vec<f64>,
+    },
+    /// Write the radius to the file as they are selected
+    Stream(NpyStreamWriter),
+}
+
+impl RadiusOutput {
+    fn reserve(&mut self, additional: usize) {
+        if let RadiusOutput::Memory { radius, .. } = self {
+            radius.reserve(additional);
+        }
+    }
+
+    fn push(&mut self, radius2: f64) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            RadiusOutput::Discard => {}
+            RadiusOutput::Memory { radius, .. } => radius.push(radius2),
+            RadiusOutput::Stream(writer) => writer.push(radius2)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            RadiusOutput::Discard => {}
+            RadiusOutput::Memory { path, radius } => ndarray_npy::write_npy(path, &Array1::from(radius))?,
+            RadiusOutput::Stream(writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+/// Number of points to select, either given directly or as a percentage of
+/// the dataset
+#[derive(Debug, Clone, Copy)]
+enum SelectCount {
+    Absolute(usize),
+    /// Fraction of the points, in (0, 1]
+    Fraction(f64),
+}
+
+impl std::str::FromStr for SelectCount {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<SelectCount, String> {
+        if let Some(percent) = value.strip_suffix('%') {
+            let percent = percent.trim().parse::<f64>().map_err(|e| format!("invalid percentage '{}': {}", value, e))?;
+            if !(percent > 0.0 && percent <= 100.0) {
+                return Err(format!("the percentage must be in (0, 100], got {}", value));
+            }
+            Ok(SelectCount::Fraction(percent / 100.0))
+        } else {
+            value.parse().map(SelectCount::Absolute).map_err(|e| format!("invalid number of points '{}': {}", value, e))
+        }
+    }
+}
+
+impl SelectCount {
+    /// Get the number of points to select from a dataset with `n_points`.
+    /// Fractions are rounded up, to select at least one point.
+    fn resolve(self, n_points: usize) -> usize {
+        match self {
+            SelectCount::Absolute(n) => n,
+            SelectCount::Fraction(fraction) => ((fraction * n_points as f64).ceil() as usize).clamp(1, n_points.max(1)),
+        }
+    }
+}
+
+/// Periodic checkpoint of the selection, to continue it after a crash
+struct Checkpoint {
+    path: String,
+    every: usize,
+    /// Radius that triggered the selection of each point, alongside the
+    /// state of the decomposer in the checkpoint
+    radius2: Vec<f64>,
+}
+
+#[cfg(feature = "serde")]
+impl Checkpoint {
+    fn radius_path(&self) -> String {
+        format!("{}.radius.npy", self.path)
+    }
+
+    /// Write the checkpoint, replacing the previous one only once the new one
+    /// is complete
+    fn write(&self, voronoi: &VoronoiDecomposer) -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = format!("{}.tmp", self.path);
+        voronoi.save(&tmp)?;
+        std::fs::rename(&tmp, &self.path)?;
+
+        let tmp = format!("{}.tmp.npy", self.radius_path());
+        ndarray_npy::write_npy(&tmp, &Array1::from(self.radius2.clone()))?;
+        std::fs::rename(&tmp, self.radius_path())?;
+        Ok(())
+    }
+
+    /// Load the decomposer and the radius from the checkpoint
+    fn load<'a>(&mut self, points: ArrayView2<'a, f64>) -> Result<VoronoiDecomposer<'a>, Box<dyn std::error::Error>> {
+        let voronoi = VoronoiDecomposer::load(&self.path, points.into())?;
+        let radius2: Array1<f64> = ndarray_npy::read_npy(self.radius_path())?;
+        if radius2.len() != voronoi.len() {
+            return Err(format!("inconsistent checkpoint files in {}", self.path).into());
+        }
+        self.radius2 = radius2.to_vec();
+        Ok(voronoi)
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+impl Checkpoint {
+    fn write(&self, _: &VoronoiDecomposer) -> Result<(), Box<dyn std::error::Error>> {
+        Err("checkpoints require the `serde` feature".into())
+    }
+
+    fn load<'a>(&mut self, _: ArrayView2<'a, f64>) -> Result<VoronoiDecomposer<'a>, Box<dyn std::error::Error>> {
+        Err("checkpoints require the `serde` feature".into())
+    }
+}
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = App::new("select-points")
+        .about("Select points with Farthest Points Sampling")
+        .arg(Arg::with_name("points")
+            .long("points")
+            .value_name("points.npy")
+            .help("2D array containing the points to select from, one point per row, \
+                   as a .npy file (containing f64, u8, i16 or i32 values) or as a \
+                   comma-separated .csv file. With the hdf5 \
+                   feature, this can also be a dataset in an HDF5 file, as \
+                   file.h5:/path/to/dataset")
+            .takes_value(true)
+            .required(true))
+        .arg(Arg::with_name("mmap")
+            .long("mmap")
+            .help("memory-map the points file instead of loading it in memory, \
+                   for datasets larger than the available memory. This requires \
+                   a .npy file containing little-endian f64 in C order."))
+        .arg(Arg::with_name("transpose")
+            .long("transpose")
+            .help("the points file contains one point per column instead of one \
+                   point per row. The points are copied to a contiguous array \
+                   with one point per row before running the selection, since \
+                   accessing the columns directly would be slow.")
+            .conflicts_with("mmap"))
+        .arg(Arg::with_name("n_select")
+            .short("n")
+            .value_name("N")
+            .help("number of points to select, including the initial point, or \
+                   percentage of the dataset (e.g. 10%, rounded up). When resuming \
+                   a previous selection, this is the number of new points.")
+            .takes_value(true)
+            .required(true))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("selected.npy")
+            .help("where to write the indexes of the selected points, as a .npy \
+                   file or as a one-column .csv file. When resuming a previous \
+                   selection, only the new points are written.")
+            .takes_value(true)
+            .default_value("selected.npy"))
+        .arg(Arg::with_name("index_dtype")
+            .long("index-dtype")
+            .value_name("DTYPE")
+            .help("integer type used for the indexes in the --output and \
+                   --assignments .npy files. This fails if an index is too large \
+                   for u32.")
+            .takes_value(true)
+            .possible_values(&["u32", "u64"])
+            .default_value("u32"))
+        .arg(Arg::with_name("output_mask")
+            .long("output-mask")
+            .value_name("mask.npy")
+            .help("write the selection as a .npy array of booleans with one entry \
+                   per point, true for the selected points, instead of writing \
+                   the indexes to --output")
+            .takes_value(true))
+        .arg(Arg::with_name("radius")
+            .long("radius")
+            .value_name("radius.npy")
+            .help("where to write the radius that triggered the selection of each \
+                   point. The initial point gets an infinite radius.")
+            .takes_value(true))
+        .arg(Arg::with_name("radius_squared")
+            .long("radius-squared")
+            .help("write the squared radius to the --radius file instead of the \
+                   radius, as previous versions of this tool did")
+            .requires("radius"))
+        .arg(Arg::with_name("stream_radius")
+            .long("stream-radius")
+            .help("write the radius of the selected points to the --radius file \
+                   during the selection, instead of keeping them in memory until \
+                   the end. The file is a valid .npy file containing all the \
+                   radius up to the last flush (every 1000 selected points).")
+            .requires("radius"))
+        .arg(Arg::with_name("convergence")
+            .long("convergence")
+            .value_name("curve.npy")
+            .help("write the convergence curve of the selection as a .npy array \
+                   with two columns: the number of selected points n, and the \
+                   radius (largest distance to the first n selected points) \
+                   that triggered the selection of the next point. The radius is \
+                   squared with --radius-squared.")
+            .takes_value(true))
+        .arg(Arg::with_name("assignments")
+            .long("assignments")
+            .value_name("assignments.npy")
+            .help("where to write the Voronoï cell of each point, as the index of \
+                   the closest selected point in the list of selected points \
+                   (including previously selected points when resuming)")
+            .takes_value(true))
+        .arg(Arg::with_name("cutoff")
+            .long("cutoff")
+            .value_name("R")
+            .help("stop the selection once all points are within this distance of \
+                   a selected point. Points farther than R from all other points \
+                   are outliers, and are never selected.")
+            .takes_value(true))
+        .arg(Arg::with_name("outliers")
+            .long("outliers")
+            .value_name("outliers.npy")
+            .help("where to write the indexes of the outliers found with --cutoff")
+            .takes_value(true)
+            .requires("cutoff"))
+        .arg(Arg::with_name("output_archive")
+            .long("output-archive")
+            .value_name("result.npz")
+            .help("also write the indexes, radius and assignments to a single .npz \
+                   file, together with the shape of the dataset, the metric, the \
+                   initial point and the version of this tool")
+            .takes_value(true))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("only read the header of the points file, print an estimate \
+                   of the memory and time required for the selection, and exit. \
+                   This requires a .npy file."))
+        .arg(Arg::with_name("progress")
+            .long("progress")
+            .help("display a progress bar during the selection. This is disabled \
+                   if the standard output is not a terminal."))
+        .arg(Arg::with_name("initial")
+            .long("initial")
+            .value_name("INDEX")
+            .help("index of the initial point")
+            .takes_value(true)
+            .default_value("0"))
+        .arg(Arg::with_name("random_seed")
+            .long("random-seed")
+            .value_name("SEED")
+            .help("pick the initial point at random, using this seed for the \
+                   random number generator. The chosen initial point is printed, \
+                   and is the first index in the output.")
+            .takes_value(true)
+            .conflicts_with_all(&["initial", "resume"]))
+        .arg(Arg::with_name("resume")
+            .long("resume")
+            .value_name("selected.npy")
+            .help("continue a previous selection, using the indexes in this file \
+                   as already selected points. The first index is used as the \
+                   initial point.")
+            .takes_value(true)
+            .conflicts_with("initial"))
+        .arg(Arg::with_name("checkpoint")
+            .long("checkpoint")
+            .value_name("checkpoint.bin")
+            .help("periodically save the state of the selection to this file \
+                   (and the radius to checkpoint.bin.radius.npy). This requires \
+                   the serde feature.")
+            .takes_value(true)
+            .conflicts_with("resume"))
+        .arg(Arg::with_name("checkpoint_every")
+            .long("checkpoint-every")
+            .value_name("K")
+            .help("number of selected points between two checkpoints")
+            .takes_value(true)
+            .default_value("1000"))
+        .arg(Arg::with_name("resume_checkpoint")
+            .long("resume-checkpoint")
+            .help("if the --checkpoint file exists, continue the selection from \
+                   it until a total of N points are selected. The outputs contain \
+                   all the selected points, including the ones from the \
+                   checkpoint.")
+            .requires("checkpoint")
+            .conflicts_with_all(&["initial", "random_seed"]))
+        .arg(Arg::with_name("verbose")
+            .short("v")
+            .multiple(true)
+            .help("print debug messages from the selection, use -vv to also \
+                   print a message for each selected point. RUST_LOG can be \
+                   used for finer control."))
+        .get_matches();
+
+    let level = match matches.occurrences_of("verbose") {
+        0 => "warn",
+        1 => "debug",
+        _ => "trace",
+    };
+    env_logger::Builder::new().parse_filters(level).parse_default_env().init();
+
+    let n_select: SelectCount = matches.value_of("n_select").expect("missing n_select").parse()?;
+    let initial = value_t!(matches, "initial", usize).unwrap_or_else(|e| e.exit());
+    let index_dtype = value_t!(matches, "index_dtype", IndexType).unwrap_or_else(|e| e.exit());
+
+    let points_path = matches.value_of("points").expect("missing points");
+    if matches.is_present("dry_run") {
+        return dry_run(points_path, n_select, matches.is_present("mmap"), matches.is_present("transpose"));
+    }
+
+    let mapped;
+    let mut loaded;
+    let points: ArrayView2<f64> = if matches.is_present("mmap") {
+        mapped = farthest_sampler::io::MappedPoints::open(points_path)?;
+        mapped.view()?
+    } else {
+        loaded = farthest_sampler::io::read_points(points_path)?;
+        if matches.is_present("transpose") {
+            loaded = loaded.reversed_axes().as_standard_layout().into_owned();
+        }
+        loaded.view()
+    };
+    let n_select = n_select.resolve(points.nrows());
+
+    let mut indexes = Vec::new();
+    let mut radius = match matches.value_of("radius") {
+        Some(path) if matches.is_present("stream_radius") => {
+            RadiusOutput::Stream(NpyStreamWriter::create(path, 1000)?)
+        }
+        Some(path) => RadiusOutput::Memory { path: path.into(), radius: Vec::new() },
+        None => RadiusOutput::Discard,
+    };
+    let squared = matches.is_present("radius_squared");
+    // radius of all the points in `indexes`, for the outputs needing them at
+    // the end of the selection
+    let mut all_radius = if matches.is_present("output_archive") || matches.is_present("convergence") {
+        Some(Vec::new())
+    } else {
+        None
+    };
+    let mut checkpoint = match matches.value_of("checkpoint") {
+        Some(path) => {
+            if cfg!(not(feature = "serde")) {
+                return Err("--checkpoint requires the `serde` feature".into());
+            }
+            let every = value_t!(matches, "checkpoint_every", usize).unwrap_or_else(|e| e.exit());
+            if every == 0 {
+                return Err("--checkpoint-every must be positive".into());
+            }
+            Some(Checkpoint { path: path.into(), every, radius2: Vec::new() })
+        }
+        None => None,
+    };
+
+    let resume_checkpoint = match checkpoint {
+        Some(ref mut checkpoint) if matches.is_present("resume_checkpoint") && Path::new(&checkpoint.path).exists() => {
+            let voronoi = checkpoint.load(points.view())?;
+            indexes.extend_from_slice(voronoi.selected());
+            for &radius2 in &checkpoint.radius2 {
+                let value = if squared { radius2 } else { radius2.sqrt() };
+                radius.push(value)?;
+                if let Some(ref mut all_radius) = all_radius {
+                    all_radius.push(value);
+                }
+            }
+            println!("resuming from {} with {} selected points", checkpoint.path, voronoi.len());
+
+            let n_new = n_select.saturating_sub(voronoi.len());
+            Some((voronoi, n_new))
+        }
+        _ => None,
+    };
+
+    let (mut voronoi, n_new) = if let Some(resumed) = resume_checkpoint {
+        resumed
+    } else if let Some(path) = matches.value_of("resume") {
+        let previous = farthest_sampler::io::read_indices(path)?;
+        let voronoi = VoronoiDecomposer::new_from_selection(points.view().into(), &previous)?;
+        (voronoi, n_select)
+    } else {
+        if n_select == 0 {
+            return Err("can not select 0 points".into());
+        }
+
+        let initial = if matches.is_present("random_seed") {
+            let seed = value_t!(matches, "random_seed", u64).unwrap_or_else(|e| e.exit());
+            let initial = StdRng::seed_from_u64(seed).gen_range(0..points.nrows().max(1));
+            println!("initial point: {}", initial);
+            initial
+        } else {
+            initial
+        };
+
+        indexes.push(initial);
+        radius.push(f64::INFINITY)?;
+        if let Some(ref mut all_radius) = all_radius {
+            all_radius.push(f64::INFINITY);
+        }
+        if let Some(ref mut checkpoint) = checkpoint {
+            checkpoint.radius2.push(f64::INFINITY);
+        }
+        (VoronoiDecomposer::try_new(points.view().into(), initial)?, n_select - 1)
+    };
+
+    if matches.is_present("cutoff") {
+        let cutoff = value_t!(matches, "cutoff", f64).unwrap_or_else(|e| e.exit());
+        if cutoff.is_nan() || cutoff < 0.0 {
+            return Err(format!("the cutoff can not be negative, got {}", cutoff).into());
+        }
+        voronoi.set_cutoff(cutoff);
+    }
+
+    if voronoi.len() + n_new > points.nrows() {
+        return Err(format!(
+            "can not select {} points out of {}", voronoi.len() + n_new, points.nrows()
+        ).into());
+    }
+    voronoi.reserve(n_new);
+    indexes.reserve(n_new);
+    radius.reserve(n_new);
+
+    let progress = if matches.is_present("progress") && std::io::stdout().is_terminal() {
+        let progress = ProgressBar::new(n_new as u64);
+        progress.set_style(
+            ProgressStyle::default_bar()
+                .template("{bar:40} {pos}/{len} [{elapsed_precise}, ETA {eta}] {msg}")
+                .expect("invalid progress bar template")
+        );
+        progress
+    } else {
+        ProgressBar::hidden()
+    };
+
+    let mut exhausted = false;
+    for _ in 0..n_new {
+        // this also finds the outliers for the cutoff, so the decomposer can
+        // be exhausted even if `is_exhausted` was false before
+        let (point, radius2) = match voronoi.select_with_radii(1).first() {
+            Some(&selected) => selected,
+            None => {
+                exhausted = true;
+                break;
+            }
+        };
+
+        indexes.push(point);
+        let value = if squared { radius2 } else { radius2.sqrt() };
+        radius.push(value)?;
+        if let Some(ref mut all_radius) = all_radius {
+            all_radius.push(value);
+        }
+
+        if let Some(ref mut checkpoint) = checkpoint {
+            checkpoint.radius2.push(radius2);
+            if voronoi.len() % checkpoint.every == 0 {
+                checkpoint.write(&voronoi)?;
+            }
+        }
+
+        progress.set_message(format!("radius2 = {:.6e}", radius2));
+        progress.inc(1);
+    }
+    progress.finish();
+    if matches.is_present("cutoff") {
+        if exhausted {
+            println!("all points are within the cutoff after {} selections", voronoi.len());
+        }
+        println!("found {} outliers", voronoi.outliers().len());
+    } else if exhausted {
+        println!("dataset exhausted after {} selections", voronoi.len());
+    }
+
+    if let Some(path) = matches.value_of("output_mask") {
+        ndarray_npy::write_npy(path, &voronoi.selected_mask())?;
+    } else {
+        let path = matches.value_of("output").expect("missing output");
+        farthest_sampler::io::write_indices_as(path, &indexes, index_dtype)?;
+    }
+    radius.finish()?;
+
+    if let Some(path) = matches.value_of("assignments") {
+        farthest_sampler::io::write_indices_as(path, voronoi.assignments(), index_dtype)?;
+    }
+
+    if let Some(path) = matches.value_of("outliers") {
+        farthest_sampler::io::write_indices_as(path, voronoi.outliers(), index_dtype)?;
+    }
+
+    if let (Some(path), Some(all_radius)) = (matches.value_of("convergence"), &all_radius) {
+        // points selected before `indexes` when resuming from a list of indexes
+        let offset = voronoi.len() - indexes.len();
+        let curve = ndarray::Array2::from_shape_fn((all_radius.len(), 2), |(i, j)| {
+            if j == 0 { (offset + i) as f64 } else { all_radius[i] }
+        });
+        ndarray_npy::write_npy(path, &curve)?;
+    }
+
+    if let (Some(path), Some(all_radius)) = (matches.value_of("output_archive"), &all_radius) {
+        let metadata = SelectionMetadata {
+            shape: points.dim(),
+            metric: "euclidean".into(),
+            initial: voronoi.selected()[0],
+        };
+        farthest_sampler::io::write_archive(
+            path, &indexes, all_radius, voronoi.assignments(), index_dtype, &metadata
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Print an estimate of the memory and time required to select `n_select`
+/// points from the `.npy` file at `path`, only reading the header of the file.
+/// With `transpose`, the file contains one point per column.
+fn dry_run(path: &str, n_select: SelectCount, mmap: bool, transpose: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let header = farthest_sampler::io::read_npy_header(path)?;
+    if header.shape.len() != 2 {
+        return Err(format!("expected a 2D array in {}, got shape {:?}", path, header.shape).into());
+    }
+
+    if mmap && header.descr != "<f8" {
+        return Err(format!("expected an array of f64 in {} for --mmap, got '{}'", path, header.descr).into());
+    } else if !farthest_sampler::io::NPY_POINTS_TYPES.contains(&header.descr.as_str()) {
+        return Err(format!("unsupported type '{}' in {}", header.descr, path).into());
+    }
+
+    let (n_points, n_features) = if transpose {
+        (header.shape[1], header.shape[0])
+    } else {
+        (header.shape[0], header.shape[1])
+    };
+    let n_select = n_select.resolve(n_points);
+    if n_select > n_points {
+        return Err(format!("can not select {} points out of {}", n_select, n_points).into());
+    }
+
+    let size = std::mem::size_of::<f64>();
+    let points = if mmap { 0 } else { n_points * n_features * size };
+    // norms, distance to the closest selected point, cell of each point and
+    // the list of points in each cell
+    let per_point = n_points * (size + size + 2 * std::mem::size_of::<usize>());
+    // center, farthest point, radius and list of points of each cell, the
+    // copy of the center, plus the work arrays and the output
+    let per_cell = n_select * (3 * size + std::mem::size_of::<Vec<usize>>() + n_features * size + 5 * size);
+
+    println!("points: {} x {} of f64{}", n_points, n_features, if mmap { " (memory-mapped)" } else { "" });
+    println!("estimated memory:");
+    println!("    points:         {}", format_bytes(points));
+    println!("    per point data: {}", format_bytes(per_point));
+    println!("    per cell data:  {}", format_bytes(per_cell));
+    println!("    total:          {}", format_bytes(points + per_point + per_cell));
+
+    // measure the time needed to compute a single distance with this number
+    // of features
+    let rows = ndarray::Array2::from_shape_fn((1024, n_features), |(i, j)| (i * j) as f64);
+    let start = std::time::Instant::now();
+    let mut n_distances = 0;
+    let mut sum = 0.0;
+    while start.elapsed().as_secs_f64() < 0.05 {
+        for row in rows.rows() {
+            sum += row.dot(&rows.row(0));
+        }
+        n_distances += rows.nrows();
+    }
+    let per_distance = start.elapsed().as_secs_f64() / n_distances as f64;
+    // use the sum to prevent the compiler from removing the loop
+    assert!(sum.is_finite());
+
+    let worst_case = per_distance * n_points as f64 * n_select.saturating_sub(1) as f64;
+    println!(
+        "estimated time: at most {:.1}s on a single thread, the Voronoï decomposition \
+         usually needs only a fraction of the distances", worst_case
+    );
+
+    Ok(())
+}
+
+/// Format a number of bytes with binary units
+fn format_bytes(bytes: usize) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, units[unit])
+}
@@ -0,0 +1,376 @@
+use ndarray::{CowArray, Ix2};
+
+use crate::{Float, Metric, VoronoiDecomposer, VoronoiError};
+use crate::metric::{Periodic, StableEuclidean};
+
+/// Builder for a `VoronoiDecomposer`, giving a single entry point to
+/// configure the metric, the initial point and the pre-allocated capacity.
+///
+/// ```
+/// # use farthest_sampler::VoronoiDecomposerBuilder;
+/// # use farthest_sampler::metric::Manhattan;
+/// let points = ndarray::Array2::<f64>::zeros((10, 3));
+/// let voronoi = VoronoiDecomposerBuilder::new()
+///     .initial(3)
+///     .metric(Manhattan)
+///     .expected_selections(5)
+///     .build(points.view().into())
+///     .unwrap();
+///
+/// assert_eq!(voronoi.selected(), [3]);
+/// ```
+pub struct VoronoiDecomposerBuilder<'a, T: Float = f64> {
+    initial: usize,
+    metric: Option<Box<dyn Metric<T> + 'a>>,
+    expected_selections: usize,
+    block_size: Option<usize>,
+    /// Use compensated summation for the default Euclidean distance
+    stable_sum: bool,
+    /// Maximal distance for the selection, if any
+    cutoff: Option<T>,
+    /// Minimal squared distance between selected points, if any
+    min_separation2: Option<T>,
+    /// Number of dimensions and seed of the random projection, if any
+    projection: Option<(usize, u64)>,
+    /// Error from one of the setters, reported by `build`
+    error: Option<VoronoiError>,
+}
+
+impl<'a, T: Float> Default for VoronoiDecomposerBuilder<'a, T> {
+    fn default() -> Self {
+        VoronoiDecomposerBuilder::new()
+    }
+}
+
+impl<'a, T: Float> VoronoiDecomposerBuilder<'a, T> {
+    /// Create a new builder, using the Euclidean distance and the first point
+    /// as initial point
+    pub fn new() -> VoronoiDecomposerBuilder<'a, T> {
+        VoronoiDecomposerBuilder {
+            initial: 0,
+            metric: None,
+            expected_selections: 0,
+            block_size: None,
+            stable_sum: false,
+            cutoff: None,
+            min_separation2: None,
+            projection: None,
+            error: None,
+        }
+    }
+
+    /// Use the point at index `initial` as the first selected point
+    pub fn initial(mut self, initial: usize) -> Self {
+        self.initial = initial;
+        return self;
+    }
+
+    /// Use a custom `metric` to compute distances, see
+    /// [`VoronoiDecomposer::new_with_metric`]. This replaces any metric set
+    /// previously, including a periodic one.
+    pub fn metric<M: Metric<T> + 'a>(mut self, metric: M) -> Self {
+        self.metric = Some(Box::new(metric));
+        self.error = None;
+        return self;
+    }
+
+    /// Use compensated (Kahan) summation when computing Euclidean distances,
+    /// see [`StableEuclidean`]. This gives more accurate and reproducible
+    /// radius at the cost of some speed.
+    ///
+    /// This only changes how the default Euclidean distance is computed, and
+    /// is ignored if a custom or periodic metric is set. It can not be used
+    /// together with `random_projection`.
+    pub fn stable_sum(mut self, stable_sum: bool) -> Self {
+        self.stable_sum = stable_sum;
+        return self;
+    }
+
+    /// Reserve space for `expected_selections` selected points when
+    /// building the decomposer
+    pub fn expected_selections(mut self, expected_selections: usize) -> Self {
+        self.expected_selections = expected_selections;
+        return self;
+    }
+
+    /// Set the number of points processed together when computing distances,
+    /// see [`VoronoiDecomposer::set_block_size`]
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.block_size = Some(block_size);
+        return self;
+    }
+
+    /// Stop the selection once all points are within `cutoff` of a selected
+    /// point, treating the points farther than `cutoff` from all other points
+    /// as outliers, see [`VoronoiDecomposer::set_cutoff`]
+    ///
+    /// # Panics
+    ///
+    /// If `cutoff` is negative or NaN.
+    pub fn cutoff(mut self, cutoff: T) -> Self {
+        assert!(cutoff >= T::zero(), "the cutoff can not be negative");
+        self.cutoff = Some(cutoff);
+        return self;
+    }
+
+    /// Never select points closer than `min_separation2` (squared distance)
+    /// to an already selected point, see
+    /// [`VoronoiDecomposer::set_min_separation2`]
+    ///
+    /// # Panics
+    ///
+    /// If `min_separation2` is negative or NaN.
+    pub fn min_separation2(mut self, min_separation2: T) -> Self {
+        assert!(min_separation2 >= T::zero(), "the minimal separation can not be negative");
+        self.min_separation2 = Some(min_separation2);
+        return self;
+    }
+
+    /// Use a random (Johnson-Lindenstrauss) projection of the points to
+    /// `target_dim` dimensions to skip some of the distance computations,
+    /// using `seed` to generate the projection matrix. This makes the
+    /// selection cheaper for high-dimensional points.
+    ///
+    /// When adding a new point, the projected distances are used to skip the
+    /// points which are clearly farther from the new point than from their
+    /// current cell center, and exact distances are computed for the other
+    /// points. All the distances, radii and coverage reported by the
+    /// decomposer are exact, but a point can rarely stay in a cell when it
+    /// should move to a closer center, which makes the selection approximate.
+    /// This can only be used with the Euclidean distance, and requires the
+    /// `random-projection` cargo feature.
+    ///
+    /// # Panics
+    ///
+    /// If `target_dim` is zero.
+    #[cfg(feature = "random-projection")]
+    pub fn random_projection(mut self, target_dim: usize, seed: u64) -> Self {
+        assert!(target_dim > 0, "the random projection needs at least one dimension");
+        self.projection = Some((target_dim, seed));
+        return self;
+    }
+
+    /// Create the decomposer for the given `points`. This returns an error if
+    /// the points are not compatible with the metric, or if any of the
+    /// constructor checks fails (see [`VoronoiDecomposer::try_new`]).
+    pub fn build(self, points: CowArray<'a, T, Ix2>) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let metric = match self.metric {
+            None if self.stable_sum => Some(Box::new(StableEuclidean) as Box<dyn Metric<T> + 'a>),
+            metric => metric,
+        };
+
+        let mut voronoi = match (metric, self.projection) {
+            (Some(_), Some(_)) => return Err(VoronoiError::UnsupportedProjection),
+            (Some(metric), None) => VoronoiDecomposer::try_new_with_metric(points, self.initial, metric)?,
+            #[cfg(feature = "random-projection")]
+            (None, Some((target_dim, seed))) => VoronoiDecomposer::try_new_projected(points, self.initial, target_dim, seed)?,
+            (None, _) => VoronoiDecomposer::try_new(points, self.initial)?,
+        };
+        voronoi.reserve(self.expected_selections);
+        if let Some(block_size) = self.block_size {
+            voronoi.set_block_size(block_size);
+        }
+        if let Some(cutoff) = self.cutoff {
+            voronoi.set_cutoff(cutoff);
+        }
+        if let Some(min_separation2) = self.min_separation2 {
+            voronoi.set_min_separation2(min_separation2);
+        }
+
+        return Ok(voronoi);
+    }
+}
+
+impl<'a> VoronoiDecomposerBuilder<'a, f64> {
+    /// Use a periodic simulation `cell` with the minimum image convention to
+    /// compute distances, see [`VoronoiDecomposer::new_periodic`]. The points
+    /// given to `build` must then have exactly 3 columns.
+    pub fn periodic(mut self, cell: [[f64; 3]; 3]) -> Self {
+        match Periodic::new(cell) {
+            Ok(metric) => {
+                self.metric = Some(Box::new(metric));
+                self.error = None;
+            }
+            Err(error) => {
+                self.metric = None;
+                self.error = Some(error);
+            }
+        }
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::metric::Manhattan;
+
+    use crate::voronoi::test::boston;
+
+    #[test]
+    fn builder() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposerBuilder::new().build(data.view().into()).unwrap();
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(voronoi.select(20), expected.select(20));
+
+        let mut voronoi = VoronoiDecomposerBuilder::new()
+            .initial(12)
+            .metric(Manhattan)
+            .expected_selections(20)
+            .build(data.view().into())
+            .unwrap();
+        let mut expected = VoronoiDecomposer::new_with_metric(data.view().into(), 12, Manhattan);
+        assert_eq!(voronoi.select(20), expected.select(20));
+
+        let error = VoronoiDecomposerBuilder::new()
+            .periodic([[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]])
+            .build(data.view().into())
+            .err();
+        assert_eq!(error, Some(VoronoiError::InvalidDimension { expected: 3, got: 13 }));
+
+        let error = VoronoiDecomposerBuilder::new()
+            .periodic([[10.0, 0.0, 0.0], [10.0, 0.0, 0.0], [0.0, 0.0, 10.0]])
+            .build(data.view().into())
+            .err();
+        assert_eq!(error, Some(VoronoiError::SingularCell));
+
+        let mut voronoi = VoronoiDecomposerBuilder::new()
+            .stable_sum(true)
+            .build(data.view().into())
+            .unwrap();
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(voronoi.select(20), expected.select(20));
+
+        // stable_sum does not replace the metric or hide errors
+        let mut voronoi = VoronoiDecomposerBuilder::new()
+            .metric(Manhattan)
+            .stable_sum(true)
+            .build(data.view().into())
+            .unwrap();
+        let mut expected = VoronoiDecomposer::new_with_metric(data.view().into(), 0, Manhattan);
+        assert_eq!(voronoi.select(20), expected.select(20));
+
+        let error = VoronoiDecomposerBuilder::new()
+            .periodic([[0.0; 3]; 3])
+            .stable_sum(false)
+            .build(data.view().into())
+            .err();
+        assert_eq!(error, Some(VoronoiError::SingularCell));
+
+        let error = VoronoiDecomposerBuilder::new()
+            .periodic([[0.0; 3]; 3])
+            .stable_sum(true)
+            .build(data.view().into())
+            .err();
+        assert_eq!(error, Some(VoronoiError::SingularCell));
+
+        let mut voronoi = VoronoiDecomposerBuilder::new()
+            .min_separation2(1e4)
+            .build(data.view().into())
+            .unwrap();
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(voronoi.select(100), expected.select_until(1e4));
+
+        let positions = data.slice(ndarray::s![.., ..3]);
+        let cell = [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]];
+        let mut voronoi = VoronoiDecomposerBuilder::new()
+            .periodic(cell)
+            .initial(4)
+            .build(positions.into())
+            .unwrap();
+        let mut expected = VoronoiDecomposer::new_periodic(positions.into(), 4, cell).unwrap();
+        assert_eq!(voronoi.select(20), expected.select(20));
+
+        let mut voronoi = VoronoiDecomposerBuilder::new()
+            .periodic(cell)
+            .stable_sum(true)
+            .initial(4)
+            .build(positions.into())
+            .unwrap();
+        let mut expected = VoronoiDecomposer::new_periodic(positions.into(), 4, cell).unwrap();
+        assert_eq!(voronoi.select(20), expected.select(20));
+    }
+
+    #[test]
+    #[cfg(feature = "random-projection")]
+    fn random_projection() {
+        use ndarray::Array2;
+        use crate::voronoi::test::random_points;
+
+        /// Largest distance (squared) between any point and the closest
+        /// point in `selected`
+        fn covering_radius2(points: &Array2<f64>, selected: &[usize]) -> f64 {
+            points.rows().into_iter().map(|point| {
+                selected.iter().map(|&s| {
+                    let diff = &point - &points.row(s);
+                    diff.dot(&diff)
+                }).fold(f64::INFINITY, f64::min)
+            }).fold(0.0, f64::max)
+        }
+
+        // points around 20 random centers in 512 dimensions
+        let centers = random_points(20, 512, 7);
+        let noise = random_points(1000, 512, 8);
+        let data = Array2::from_shape_fn((1000, 512), |(i, j)| centers[[i % 20, j]] + 0.1 * noise[[i, j]]);
+
+        let mut exact = VoronoiDecomposer::new(data.view().into(), 0);
+        exact.enable_trace();
+        exact.select(39);
+
+        let mut approximate = VoronoiDecomposerBuilder::new()
+            .random_projection(64, 42)
+            .build(data.view().into())
+            .unwrap();
+        approximate.enable_trace();
+        approximate.select(39);
+
+        let exact_radius2 = covering_radius2(&data, exact.selected());
+        let approximate_radius2 = covering_radius2(&data, approximate.selected());
+        assert!(approximate_radius2 < 1.5 * exact_radius2, "{} vs {}", approximate_radius2, exact_radius2);
+
+        // the reported distances are exact distances to the cell centers,
+        // and can only over-estimate the covering radius
+        let nearest_distances2 = approximate.nearest_distances2();
+        for (point, &center) in approximate.assignments().iter().enumerate() {
+            let diff = &data.row(point) - &data.row(approximate.selected()[center]);
+            assert!(f64::abs(diff.dot(&diff) - nearest_distances2[point]) < 1e-9);
+        }
+        let (max_radius, _) = approximate.kcenter_bound();
+        assert!(max_radius * max_radius >= approximate_radius2 - 1e-9);
+        assert!(max_radius * max_radius < 1.5 * exact_radius2);
+
+        // the projection skips some of the exact distance computations
+        let n_distances = |trace: Vec<crate::AddPointEvent<f64>>| trace.iter().map(|event| event.n_distances).sum::<usize>();
+        let exact_distances = n_distances(exact.take_trace());
+        let approximate_distances = n_distances(approximate.take_trace());
+        assert!(approximate_distances < exact_distances);
+
+        // the same seed gives the same selection
+        let mut again = VoronoiDecomposerBuilder::new()
+            .random_projection(64, 42)
+            .build(data.view().into())
+            .unwrap();
+        again.select(39);
+        assert_eq!(again.selected(), approximate.selected());
+
+        let error = VoronoiDecomposerBuilder::new()
+            .random_projection(64, 42)
+            .metric(Manhattan)
+            .build(data.view().into())
+            .err();
+        assert_eq!(error, Some(VoronoiError::UnsupportedProjection));
+
+        let error = VoronoiDecomposerBuilder::new()
+            .random_projection(64, 42)
+            .stable_sum(true)
+            .build(data.view().into())
+            .err();
+        assert_eq!(error, Some(VoronoiError::UnsupportedProjection));
+    }
+}
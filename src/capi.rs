@@ -0,0 +1,133 @@
+//! C API for the Voronoï decomposer, to use it from C, C++ or Fortran. The
+//! corresponding declarations are in `include/farthest_sampler.h`.
+//!
+//! Panics must not unwind into the calling C code, so all functions catch
+//! them and return the same value as for other errors (NULL or -1).
+#![allow(clippy::missing_safety_doc)]
+
+use std::os::raw::c_int;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use ndarray::ArrayView2;
+
+use crate::VoronoiDecomposer;
+
+/// Opaque decomposer type for the C API. The points are borrowed from the
+/// caller, who must keep them alive until `vfps_free` is called.
+pub struct vfps_decomposer {
+    decomposer: VoronoiDecomposer<'static, f64>,
+}
+
+/// Run `function`, returning `error` if it panics
+fn catch_panic<R>(error: R, function: impl FnOnce() -> R) -> R {
+    return catch_unwind(AssertUnwindSafe(function)).unwrap_or(error);
+}
+
+/// Create a new decomposer for the `n_rows x n_cols` points stored in
+/// row-major order in `points`, starting from the point at index `initial`.
+///
+/// This returns NULL if `points` is NULL, if `n_rows` is zero, if
+/// `n_rows * n_cols` overflows, if `initial` is out of bounds, or if the
+/// distance between the initial point and another point is NaN or infinite.
+#[no_mangle]
+pub unsafe extern "C" fn vfps_new(points: *const f64, n_rows: usize, n_cols: usize, initial: usize) -> *mut vfps_decomposer {
+    return catch_panic(std::ptr::null_mut(), || {
+        let size = n_rows.checked_mul(n_cols).filter(|&size| size <= isize::MAX as usize / std::mem::size_of::<f64>());
+        if points.is_null() || size.is_none() {
+            return std::ptr::null_mut();
+        }
+
+        let points = ArrayView2::from_shape_ptr((n_rows, n_cols), points);
+        return match VoronoiDecomposer::try_new(points.into(), initial) {
+            Ok(decomposer) => Box::into_raw(Box::new(vfps_decomposer { decomposer: decomposer })),
+            Err(_) => std::ptr::null_mut(),
+        };
+    });
+}
+
+/// Add the point at index `point` to the selected points. This returns 0 on
+/// success and -1 if the decomposer is NULL, the index is out of bounds, the
+/// point is already selected or the new distances are NaN or infinite.
+#[no_mangle]
+pub unsafe extern "C" fn vfps_add_point(decomposer: *mut vfps_decomposer, point: usize) -> c_int {
+    return catch_panic(-1, || {
+        let decomposer = match decomposer.as_mut() {
+            Some(decomposer) => &mut decomposer.decomposer,
+            None => return -1,
+        };
+
+        return match decomposer.try_add_point(point) {
+            Ok(()) => 0,
+            Err(_) => -1,
+        };
+    });
+}
+
+/// Select up to `n` new points, and return their indexes in a new buffer.
+/// The number of selected points is written to `n_selected`.
+///
+/// The caller owns the returned buffer, and must release it with
+/// `vfps_free_indices`. This returns NULL if one of the pointers is NULL.
+#[no_mangle]
+pub unsafe extern "C" fn vfps_select(decomposer: *mut vfps_decomposer, n: usize, n_selected: *mut usize) -> *mut usize {
+    return catch_panic(std::ptr::null_mut(), || {
+        let decomposer = match decomposer.as_mut() {
+            Some(decomposer) => &mut decomposer.decomposer,
+            None => return std::ptr::null_mut(),
+        };
+
+        if n_selected.is_null() {
+            return std::ptr::null_mut();
+        }
+
+        let selected = decomposer.select(n).into_boxed_slice();
+        *n_selected = selected.len();
+        return Box::into_raw(selected) as *mut usize;
+    });
+}
+
+/// Release a buffer of `n_selected` indexes returned by `vfps_select`
+#[no_mangle]
+pub unsafe extern "C" fn vfps_free_indices(indices: *mut usize, n_selected: usize) {
+    catch_panic((), || {
+        if !indices.is_null() {
+            let slice = std::ptr::slice_from_raw_parts_mut(indices, n_selected);
+            std::mem::drop(Box::from_raw(slice));
+        }
+    });
+}
+
+/// Get the squared radius of each Voronoï cell, in selection order. The
+/// number of cells is written to `n_cells`.
+///
+/// The returned pointer is owned by the decomposer, and is only valid until
+/// the next call to `vfps_add_point`, `vfps_select` or `vfps_free`. This
+/// returns NULL if one of the pointers is NULL.
+#[no_mangle]
+pub unsafe extern "C" fn vfps_radii(decomposer: *const vfps_decomposer, n_cells: *mut usize) -> *const f64 {
+    return catch_panic(std::ptr::null(), || {
+        let decomposer = match decomposer.as_ref() {
+            Some(decomposer) => &decomposer.decomposer,
+            None => return std::ptr::null(),
+        };
+
+        if n_cells.is_null() {
+            return std::ptr::null();
+        }
+
+        let radius2 = decomposer.cells().radius2;
+        *n_cells = radius2.len();
+        return radius2.as_ptr();
+    });
+}
+
+/// Release a decomposer created with `vfps_new`. The points can be released
+/// after this call.
+#[no_mangle]
+pub unsafe extern "C" fn vfps_free(decomposer: *mut vfps_decomposer) {
+    catch_panic((), || {
+        if !decomposer.is_null() {
+            std::mem::drop(Box::from_raw(decomposer));
+        }
+    });
+}
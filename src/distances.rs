@@ -0,0 +1,780 @@
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use ndarray::{ArrayView1, ArrayView2, Axis, CowArray, Ix2, Slice};
+#[cfg(feature = "random-projection")]
+use rand::{Rng, SeedableRng};
+
+use crate::{Float, Metric, VoronoiError};
+
+/// Source of distances between the points used in a `VoronoiDecomposer`.
+///
+/// The square root of `distance2` must follow the triangle inequality for the
+/// pruning in the Voronoï decomposition to be valid.
+pub(crate) trait Distances<T>: Send + Sync + std::fmt::Debug {
+    /// Get the number of points
+    fn n_points(&self) -> usize;
+
+    /// Get the squared distance between the points at indexes `i` and `j`
+    fn distance2(&self, i: usize, j: usize) -> T;
+
+    /// Compute the squared distances between the point at index `i` and all
+    /// the `points`, and add them at the end of `distances2`
+    fn distances2_to(&self, i: usize, points: &[usize], distances2: &mut Vec<T>) {
+        distances2.extend(points.iter().map(|&j| self.distance2(i, j)));
+    }
+
+    /// Record that the point at index `i` was selected as a new center.
+    /// Implementations can keep a copy of the selected points to access them
+    /// faster in `center_distances2` and `distances2_between_centers`.
+    fn push_center(&mut self, _i: usize) {}
+
+    /// Remove the last center added with `push_center`
+    fn pop_center(&mut self) {}
+
+    /// Same as `distances2_to`, where the point at index `i` is the center
+    /// at position `center` in the list of selected centers
+    fn center_distances2(&self, _center: usize, i: usize, points: &[usize], distances2: &mut Vec<T>) {
+        self.distances2_to(i, points, distances2);
+    }
+
+    /// Remove from `candidates` some of the points which are farther from the
+    /// point at index `i` (the center at position `center`) than from their
+    /// current center, at the squared distance `haussdorf[point]`. The exact
+    /// distances are then only computed for the remaining candidates.
+    fn prefilter(&self, _center: usize, _i: usize, _candidates: &mut Vec<usize>, _haussdorf: &[T]) {}
+
+    /// Compute the squared distances between the point at index `i`, which
+    /// is the center at position `center`, and the first `centers.len()`
+    /// selected `centers`, adding them at the end of `distances2`
+    fn distances2_between_centers(&self, _center: usize, i: usize, centers: &[usize], distances2: &mut Vec<T>) {
+        self.distances2_to(i, centers, distances2);
+    }
+
+    /// Compute the squared distances between an arbitrary `vector`, which
+    /// does not need to be one of the points, and the selected `centers`.
+    /// This returns an error if `vector` does not have the right number of
+    /// features, or if these distances can not be computed for new vectors.
+    fn distances2_to_vector(&self, _centers: &[usize], _vector: ArrayView1<'_, T>) -> Result<Vec<T>, VoronoiError> {
+        return Err(VoronoiError::UnsupportedQuery);
+    }
+
+    /// Add new points at the end of the current set of points
+    fn extend(&mut self, points: ArrayView2<'_, T>) -> Result<(), VoronoiError>;
+
+    /// Remove all the points after the first `n_points`, undoing a previous
+    /// call to `extend`
+    fn truncate(&mut self, n_points: usize);
+
+    /// Get the coordinates of the points, if they are available
+    fn points(&self) -> Option<ArrayView2<'_, T>>;
+
+    /// Are these the plain (not normalized) Euclidean distances between the
+    /// points? Only these distances can be restored from a saved state.
+    #[cfg(feature = "serde")]
+    fn is_euclidean(&self) -> bool {
+        false
+    }
+}
+
+/// Check that `vector` has the same number of features as `points`
+fn check_features<T, U>(points: &CowArray<'_, T, Ix2>, vector: ArrayView1<'_, U>) -> Result<(), VoronoiError> {
+    if vector.len() != points.ncols() {
+        return Err(VoronoiError::InvalidDimension { expected: points.ncols(), got: vector.len() });
+    }
+    return Ok(());
+}
+
+/// Append the rows of `new` to `points`, making `points` owned
+fn append_rows<T: Float>(points: &mut CowArray<'_, T, Ix2>, new: ArrayView2<'_, T>) -> Result<(), VoronoiError> {
+    if new.ncols() != points.ncols() {
+        return Err(VoronoiError::InvalidDimension { expected: points.ncols(), got: new.ncols() });
+    }
+
+    let mut owned = std::mem::replace(points, CowArray::from(ndarray::Array2::zeros((0, 0)))).into_owned();
+    owned.append(Axis(0), new).expect("the number of columns was already checked");
+    *points = owned.into();
+
+    return Ok(());
+}
+
+/// Euclidean distances between points, using pre-computed norms
+#[derive(Debug)]
+pub(crate) struct EuclideanDistances<'a, T> {
+    /// Input points
+    points: CowArray<'a, T, Ix2>,
+    /// Norm of the vector from origin for each points
+    norms: Vec<T>,
+    /// Should new points be normalized when extending the points?
+    normalize: bool,
+    /// Contiguous copy of the selected centers, one after the other
+    centers: Vec<T>,
+}
+
+impl<'a, T: Float> EuclideanDistances<'a, T> {
+    pub fn new(points: CowArray<'a, T, Ix2>) -> EuclideanDistances<'a, T> {
+        let norms = into_par_iter!(points.axis_iter(Axis(0)))
+            .map(|row| row.dot(&row))
+            .collect();
+
+        EuclideanDistances {
+            points: points,
+            norms: norms,
+            normalize: false,
+            centers: Vec::new(),
+        }
+    }
+
+    /// Get the copy of the center at position `center`
+    fn center(&self, center: usize) -> ArrayView1<'_, T> {
+        let n_features = self.points.ncols();
+        return ArrayView1::from(&self.centers[center * n_features..(center + 1) * n_features]);
+    }
+
+    /// Create Euclidean distances between the normalized `points`, which
+    /// corresponds to the cosine distance. This returns an error if one of
+    /// the points is the zero vector.
+    pub fn new_normalized(points: CowArray<'a, T, Ix2>) -> Result<EuclideanDistances<'a, T>, VoronoiError> {
+        let mut normalized = points.into_owned();
+        normalize_rows(&mut normalized, 0)?;
+
+        let mut distances = EuclideanDistances::new(normalized.into());
+        distances.normalize = true;
+        return Ok(distances);
+    }
+}
+
+/// Normalize all the rows of `points`, the first row having the index
+/// `first_index` for error messages
+fn normalize_rows<T: Float>(points: &mut ndarray::Array2<T>, first_index: usize) -> Result<(), VoronoiError> {
+    for (index, mut row) in points.rows_mut().into_iter().enumerate() {
+        let norm = row.dot(&row).sqrt();
+        if norm == T::zero() {
+            return Err(VoronoiError::ZeroVector { index: first_index + index });
+        }
+        row.mapv_inplace(|v| v / norm);
+    }
+    return Ok(());
+}
+
+impl<'a, T: Float> Distances<T> for EuclideanDistances<'a, T> {
+    fn n_points(&self) -> usize {
+        self.points.nrows()
+    }
+
+    fn distance2(&self, i: usize, j: usize) -> T {
+        let dot = self.points.row(i).dot(&self.points.row(j));
+        self.norms[i] + self.norms[j] - (dot + dot)
+    }
+
+    fn push_center(&mut self, i: usize) {
+        self.centers.extend(self.points.row(i).iter().copied());
+    }
+
+    fn pop_center(&mut self) {
+        let n_centers = self.centers.len() / self.points.ncols();
+        self.centers.truncate((n_centers - 1) * self.points.ncols());
+    }
+
+    #[cfg(not(feature = "blas"))]
+    fn center_distances2(&self, center: usize, i: usize, points: &[usize], distances2: &mut Vec<T>) {
+        // read the center from the contiguous copy, instead of a random row
+        // in the full set of points
+        let center = self.center(center);
+        distances2.extend(points.iter().map(|&j| {
+            let dot = center.dot(&self.points.row(j));
+            self.norms[i] + self.norms[j] - (dot + dot)
+        }));
+    }
+
+    #[cfg(feature = "blas")]
+    fn center_distances2(&self, center: usize, i: usize, points: &[usize], distances2: &mut Vec<T>) {
+        // gather the points in a contiguous matrix to compute all the dot
+        // products with a single BLAS matrix-vector product
+        let rows = self.points.select(Axis(0), points);
+        let dots = rows.dot(&self.center(center));
+        let tolerance = T::epsilon() * T::from(1024).expect("failed to convert 1024");
+        distances2.extend(points.iter().zip(dots).map(|(&j, dot)| {
+            let d2 = self.norms[i] + self.norms[j] - (dot + dot);
+            if d2 <= tolerance * (self.norms[i] + self.norms[j]) {
+                // the BLAS dot products are rounded differently from the
+                // norms, which gives non-zero distances between a point and
+                // itself or its duplicates. Use the same rounding as
+                // `distance2` for close points.
+                return self.distance2(i, j);
+            }
+            return d2;
+        }));
+    }
+
+    fn distances2_between_centers(&self, center: usize, i: usize, centers: &[usize], distances2: &mut Vec<T>) {
+        let new_center = self.center(center);
+        distances2.extend(centers.iter().enumerate().map(|(position, &j)| {
+            let dot = new_center.dot(&self.center(position));
+            self.norms[i] + self.norms[j] - (dot + dot)
+        }));
+    }
+
+    fn distances2_to_vector(&self, centers: &[usize], vector: ArrayView1<'_, T>) -> Result<Vec<T>, VoronoiError> {
+        check_features(&self.points, vector)?;
+        let vector = if self.normalize {
+            // a zero vector gives NaN distances
+            let norm = vector.dot(&vector).sqrt();
+            vector.mapv(|v| v / norm)
+        } else {
+            vector.to_owned()
+        };
+
+        let norm = vector.dot(&vector);
+        return Ok(centers.iter().enumerate().map(|(position, &i)| {
+            let dot = self.center(position).dot(&vector);
+            self.norms[i] + norm - (dot + dot)
+        }).collect());
+    }
+
+    fn extend(&mut self, points: ArrayView2<'_, T>) -> Result<(), VoronoiError> {
+        let first_index = self.points.nrows();
+        if self.normalize {
+            let mut normalized = points.to_owned();
+            normalize_rows(&mut normalized, first_index)?;
+            append_rows(&mut self.points, normalized.view())?;
+        } else {
+            append_rows(&mut self.points, points)?;
+        }
+
+        let new_points = self.points.slice(ndarray::s![first_index.., ..]);
+        self.norms.extend(new_points.rows().into_iter().map(|row| row.dot(&row)));
+        return Ok(());
+    }
+
+    fn truncate(&mut self, n_points: usize) {
+        self.points.slice_axis_inplace(Axis(0), Slice::from(..n_points));
+        self.norms.truncate(n_points);
+    }
+
+    fn points(&self) -> Option<ArrayView2<'_, T>> {
+        Some(self.points.view())
+    }
+
+    #[cfg(feature = "serde")]
+    fn is_euclidean(&self) -> bool {
+        !self.normalize
+    }
+}
+
+/// Euclidean distances between points, using a random projection of the
+/// points to fewer dimensions to skip some of the exact distances
+/// computations in `prefilter`
+#[cfg(feature = "random-projection")]
+#[derive(Debug)]
+pub(crate) struct ProjectedDistances<'a, T> {
+    /// Exact distances between the points
+    exact: EuclideanDistances<'a, T>,
+    /// Distances between the projected points
+    projected: EuclideanDistances<'a, T>,
+    /// Projection matrix, with one column per projected dimension
+    projection: ndarray::Array2<T>,
+    /// Candidates are skipped if their projected distance is larger than
+    /// `margin` times their current distance
+    margin: T,
+}
+
+#[cfg(feature = "random-projection")]
+impl<'a, T: Float> ProjectedDistances<'a, T> {
+    /// Project the `points` to `target_dim` dimensions, using `seed` to
+    /// generate the projection matrix
+    pub fn new(points: CowArray<'a, T, Ix2>, target_dim: usize, seed: u64) -> ProjectedDistances<'a, T> {
+        // random matrix with entries +/- 1 / sqrt(target_dim), which
+        // approximately preserves the Euclidean distances (Achlioptas, 2003)
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let dimension = T::from(target_dim).expect("failed to convert dimension").sqrt();
+        let scale = dimension.recip();
+        let projection = ndarray::Array2::from_shape_simple_fn((points.ncols(), target_dim), || {
+            if rng.gen::<bool>() { scale } else { -scale }
+        });
+
+        let projected = EuclideanDistances::new(points.dot(&projection).into());
+        // the relative error on projected squared distances has a standard
+        // deviation of sqrt(2 / target_dim), this margin is almost 3 times
+        // larger
+        let four = T::from(4.0).expect("failed to convert 4");
+        ProjectedDistances {
+            exact: EuclideanDistances::new(points),
+            projected: projected,
+            projection: projection,
+            margin: T::one() + four / dimension,
+        }
+    }
+}
+
+#[cfg(feature = "random-projection")]
+impl<'a, T: Float> Distances<T> for ProjectedDistances<'a, T> {
+    fn n_points(&self) -> usize {
+        self.exact.n_points()
+    }
+
+    fn distance2(&self, i: usize, j: usize) -> T {
+        self.exact.distance2(i, j)
+    }
+
+    fn push_center(&mut self, i: usize) {
+        self.exact.push_center(i);
+        self.projected.push_center(i);
+    }
+
+    fn pop_center(&mut self) {
+        self.exact.pop_center();
+        self.projected.pop_center();
+    }
+
+    fn prefilter(&self, center: usize, i: usize, candidates: &mut Vec<usize>, haussdorf: &[T]) {
+        let mut projected_d2 = Vec::with_capacity(candidates.len());
+        self.projected.center_distances2(center, i, candidates, &mut projected_d2);
+        let mut projected_d2 = projected_d2.into_iter();
+        // keep the points with NaN distances, to report them as errors
+        candidates.retain(|&point| {
+            let d2 = projected_d2.next().expect("missing distance");
+            return d2.is_nan() || d2 <= self.margin * haussdorf[point];
+        });
+    }
+
+    fn center_distances2(&self, center: usize, i: usize, points: &[usize], distances2: &mut Vec<T>) {
+        self.exact.center_distances2(center, i, points, distances2);
+    }
+
+    fn distances2_between_centers(&self, center: usize, i: usize, centers: &[usize], distances2: &mut Vec<T>) {
+        self.exact.distances2_between_centers(center, i, centers, distances2);
+    }
+
+    fn distances2_to_vector(&self, centers: &[usize], vector: ArrayView1<'_, T>) -> Result<Vec<T>, VoronoiError> {
+        self.exact.distances2_to_vector(centers, vector)
+    }
+
+    fn extend(&mut self, points: ArrayView2<'_, T>) -> Result<(), VoronoiError> {
+        self.exact.extend(points)?;
+        return self.projected.extend(points.dot(&self.projection).view());
+    }
+
+    fn truncate(&mut self, n_points: usize) {
+        self.exact.truncate(n_points);
+        self.projected.truncate(n_points);
+    }
+
+    fn points(&self) -> Option<ArrayView2<'_, T>> {
+        self.exact.points()
+    }
+}
+
+/// Weighted sum of the squared Euclidean distances between points described
+/// by multiple sets of features (views), with one row per point in each view
+#[derive(Debug)]
+pub(crate) struct MultiViewDistances<'a, T> {
+    /// Euclidean distances in each view
+    views: Vec<EuclideanDistances<'a, T>>,
+    /// Weight of each view in the sum
+    weights: Vec<T>,
+}
+
+impl<'a, T: Float> MultiViewDistances<'a, T> {
+    /// Create the distances for the given `views`, which must all have the
+    /// same number of rows, and one weight per view
+    pub fn new(views: Vec<CowArray<'a, T, Ix2>>, weights: Vec<T>) -> MultiViewDistances<'a, T> {
+        debug_assert_eq!(views.len(), weights.len());
+        MultiViewDistances {
+            views: views.into_iter().map(EuclideanDistances::new).collect(),
+            weights: weights,
+        }
+    }
+
+    /// Get the total number of features in all views
+    fn n_features(&self) -> usize {
+        self.views.iter().map(|view| view.points.ncols()).sum()
+    }
+
+    /// Add `n` distances at the end of `distances2`, with the weighted sum
+    /// over all views of the distances computed by `compute`
+    fn sum_views<F>(&self, n: usize, distances2: &mut Vec<T>, compute: F) where F: Fn(&EuclideanDistances<'a, T>, &mut Vec<T>) {
+        let start = distances2.len();
+        distances2.resize(start + n, T::zero());
+
+        let mut view_distances2 = Vec::with_capacity(n);
+        for (view, &weight) in self.views.iter().zip(&self.weights) {
+            view_distances2.clear();
+            compute(view, &mut view_distances2);
+            for (d2, &view_d2) in distances2[start..].iter_mut().zip(&view_distances2) {
+                *d2 = *d2 + weight * view_d2;
+            }
+        }
+    }
+}
+
+impl<'a, T: Float> Distances<T> for MultiViewDistances<'a, T> {
+    fn n_points(&self) -> usize {
+        self.views[0].n_points()
+    }
+
+    fn distance2(&self, i: usize, j: usize) -> T {
+        self.views.iter().zip(&self.weights).map(|(view, &weight)| weight * view.distance2(i, j)).sum()
+    }
+
+    fn distances2_to(&self, i: usize, points: &[usize], distances2: &mut Vec<T>) {
+        self.sum_views(points.len(), distances2, |view, view_distances2| view.distances2_to(i, points, view_distances2));
+    }
+
+    fn push_center(&mut self, i: usize) {
+        for view in &mut self.views {
+            view.push_center(i);
+        }
+    }
+
+    fn pop_center(&mut self) {
+        for view in &mut self.views {
+            view.pop_center();
+        }
+    }
+
+    fn center_distances2(&self, center: usize, i: usize, points: &[usize], distances2: &mut Vec<T>) {
+        self.sum_views(points.len(), distances2, |view, view_distances2| {
+            view.center_distances2(center, i, points, view_distances2);
+        });
+    }
+
+    fn distances2_between_centers(&self, center: usize, i: usize, centers: &[usize], distances2: &mut Vec<T>) {
+        self.sum_views(centers.len(), distances2, |view, view_distances2| {
+            view.distances2_between_centers(center, i, centers, view_distances2);
+        });
+    }
+
+    fn distances2_to_vector(&self, centers: &[usize], vector: ArrayView1<'_, T>) -> Result<Vec<T>, VoronoiError> {
+        if vector.len() != self.n_features() {
+            return Err(VoronoiError::InvalidDimension { expected: self.n_features(), got: vector.len() });
+        }
+
+        let mut distances2 = vec![T::zero(); centers.len()];
+        let mut offset = 0;
+        for (view, &weight) in self.views.iter().zip(&self.weights) {
+            let n_features = view.points.ncols();
+            let view_vector = vector.slice(ndarray::s![offset..offset + n_features]);
+            for (d2, view_d2) in distances2.iter_mut().zip(view.distances2_to_vector(centers, view_vector)?) {
+                *d2 = *d2 + weight * view_d2;
+            }
+            offset += n_features;
+        }
+        return Ok(distances2);
+    }
+
+    fn extend(&mut self, points: ArrayView2<'_, T>) -> Result<(), VoronoiError> {
+        if points.ncols() != self.n_features() {
+            return Err(VoronoiError::InvalidDimension { expected: self.n_features(), got: points.ncols() });
+        }
+
+        let mut offset = 0;
+        for view in &mut self.views {
+            let n_features = view.points.ncols();
+            view.extend(points.slice(ndarray::s![.., offset..offset + n_features]))?;
+            offset += n_features;
+        }
+        return Ok(());
+    }
+
+    fn truncate(&mut self, n_points: usize) {
+        for view in &mut self.views {
+            view.truncate(n_points);
+        }
+    }
+
+    fn points(&self) -> Option<ArrayView2<'_, T>> {
+        None
+    }
+}
+
+/// Distances between points computed with an arbitrary `Metric`
+pub(crate) struct MetricDistances<'a, T, M> {
+    /// Input points
+    points: CowArray<'a, T, Ix2>,
+    /// Metric used to compute distances
+    metric: M,
+}
+
+impl<'a, T: Float, M: Metric<T>> MetricDistances<'a, T, M> {
+    pub fn new(points: CowArray<'a, T, Ix2>, metric: M) -> MetricDistances<'a, T, M> {
+        MetricDistances {
+            points: points,
+            metric: metric,
+        }
+    }
+}
+
+impl<'a, T: Float, M> std::fmt::Debug for MetricDistances<'a, T, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetricDistances")
+            .field("points", &self.points)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, T: Float, M: Metric<T>> Distances<T> for MetricDistances<'a, T, M> {
+    fn n_points(&self) -> usize {
+        self.points.nrows()
+    }
+
+    fn distance2(&self, i: usize, j: usize) -> T {
+        self.metric.distance2(self.points.row(i), self.points.row(j))
+    }
+
+    fn distances2_to_vector(&self, centers: &[usize], vector: ArrayView1<'_, T>) -> Result<Vec<T>, VoronoiError> {
+        check_features(&self.points, vector)?;
+        return Ok(centers.iter().map(|&i| self.metric.distance2(self.points.row(i), vector)).collect());
+    }
+
+    fn extend(&mut self, points: ArrayView2<'_, T>) -> Result<(), VoronoiError> {
+        return append_rows(&mut self.points, points);
+    }
+
+    fn truncate(&mut self, n_points: usize) {
+        self.points.slice_axis_inplace(Axis(0), Slice::from(..n_points));
+    }
+
+    fn points(&self) -> Option<ArrayView2<'_, T>> {
+        Some(self.points.view())
+    }
+}
+
+/// Distances in the feature space induced by a kernel, computed from the Gram
+/// matrix as `d^2(i, j) = K(i, i) + K(j, j) - 2 K(i, j)`
+#[derive(Debug)]
+pub(crate) struct GramDistances<'a, T> {
+    /// Kernel matrix between all points
+    kernel: CowArray<'a, T, Ix2>,
+    /// Diagonal of the kernel matrix
+    diagonal: Vec<T>,
+}
+
+impl<'a, T: Float> GramDistances<'a, T> {
+    pub fn new(kernel: CowArray<'a, T, Ix2>) -> GramDistances<'a, T> {
+        let diagonal = kernel.diag().to_vec();
+        GramDistances {
+            kernel: kernel,
+            diagonal: diagonal,
+        }
+    }
+}
+
+impl<'a, T: Float> Distances<T> for GramDistances<'a, T> {
+    fn n_points(&self) -> usize {
+        self.kernel.nrows()
+    }
+
+    fn distance2(&self, i: usize, j: usize) -> T {
+        let kij = self.kernel[[i, j]];
+        self.diagonal[i] + self.diagonal[j] - (kij + kij)
+    }
+
+    fn extend(&mut self, _: ArrayView2<'_, T>) -> Result<(), VoronoiError> {
+        // we would need the kernel between the new points and all the
+        // existing ones
+        return Err(VoronoiError::CanNotExtend);
+    }
+
+    fn truncate(&mut self, n_points: usize) {
+        // `extend` always fails, so there are never points to remove
+        debug_assert_eq!(n_points, self.n_points());
+    }
+
+    fn points(&self) -> Option<ArrayView2<'_, T>> {
+        None
+    }
+}
+
+/// Euclidean distances between points stored as a sparse matrix in CSR
+/// format, only iterating over the non-zero values of each point
+#[derive(Debug)]
+pub(crate) struct SparseDistances<'a, T> {
+    /// Start of each row in `indices` and `data`, with one more entry for the
+    /// end of the last row
+    indptr: &'a [usize],
+    /// Column of each non-zero value, sorted inside each row
+    indices: &'a [usize],
+    /// Non-zero values
+    data: &'a [T],
+    /// Number of columns in the matrix
+    n_features: usize,
+    /// Norm of the vector from origin for each points
+    norms: Vec<T>,
+}
+
+impl<'a, T: Float> SparseDistances<'a, T> {
+    /// Create sparse distances from the CSR arrays, checking that they are
+    /// consistent with each other and that no column index is larger than
+    /// `n_features`.
+    pub fn new(n_features: usize, indptr: &'a [usize], indices: &'a [usize], data: &'a [T]) -> Result<SparseDistances<'a, T>, VoronoiError> {
+        let invalid = |message: String| VoronoiError::InvalidSparseMatrix { message };
+
+        if indptr.is_empty() || indptr[0] != 0 {
+            return Err(invalid("indptr must start with 0".into()));
+        }
+
+        if indices.len() != data.len() {
+            return Err(invalid(format!("got {} indices for {} values", indices.len(), data.len())));
+        }
+
+        if indptr[indptr.len() - 1] != data.len() {
+            return Err(invalid(format!(
+                "the last indptr entry is {}, but there are {} values", indptr[indptr.len() - 1], data.len()
+            )));
+        }
+
+        let mut norms = Vec::with_capacity(indptr.len() - 1);
+        for (row, range) in indptr.windows(2).enumerate() {
+            if range[0] > range[1] {
+                return Err(invalid(format!("indptr is decreasing at row {}", row)));
+            }
+
+            let columns = &indices[range[0]..range[1]];
+            if columns.windows(2).any(|pair| pair[0] >= pair[1]) {
+                return Err(invalid(format!("the indices of row {} are not sorted or contain duplicates", row)));
+            }
+
+            if let Some(&column) = columns.last() {
+                if column >= n_features {
+                    return Err(invalid(format!("column {} in row {} is out of range for {} features", column, row, n_features)));
+                }
+            }
+
+            let values = &data[range[0]..range[1]];
+            norms.push(values.iter().map(|&v| v * v).sum());
+        }
+
+        return Ok(SparseDistances {
+            indptr: indptr,
+            indices: indices,
+            data: data,
+            n_features: n_features,
+            norms: norms,
+        });
+    }
+
+    /// Get the column indices and values of the non-zero entries in `row`
+    fn row(&self, row: usize) -> (&[usize], &[T]) {
+        let (start, stop) = (self.indptr[row], self.indptr[row + 1]);
+        return (&self.indices[start..stop], &self.data[start..stop]);
+    }
+
+    /// Dot product between two rows, going through the non-zero values in
+    /// both rows together
+    fn dot(&self, i: usize, j: usize) -> T {
+        let (columns_i, values_i) = self.row(i);
+        let (columns_j, values_j) = self.row(j);
+
+        let mut dot = T::zero();
+        let (mut a, mut b) = (0, 0);
+        while a < columns_i.len() && b < columns_j.len() {
+            match columns_i[a].cmp(&columns_j[b]) {
+                std::cmp::Ordering::Less => a += 1,
+                std::cmp::Ordering::Greater => b += 1,
+                std::cmp::Ordering::Equal => {
+                    dot = dot + values_i[a] * values_j[b];
+                    a += 1;
+                    b += 1;
+                }
+            }
+        }
+        return dot;
+    }
+}
+
+impl<'a, T: Float> Distances<T> for SparseDistances<'a, T> {
+    fn n_points(&self) -> usize {
+        self.norms.len()
+    }
+
+    fn distance2(&self, i: usize, j: usize) -> T {
+        let dot = self.dot(i, j);
+        self.norms[i] + self.norms[j] - (dot + dot)
+    }
+
+    fn distances2_to_vector(&self, centers: &[usize], vector: ArrayView1<'_, T>) -> Result<Vec<T>, VoronoiError> {
+        if vector.len() != self.n_features {
+            return Err(VoronoiError::InvalidDimension { expected: self.n_features, got: vector.len() });
+        }
+
+        let norm = vector.dot(&vector);
+        return Ok(centers.iter().map(|&i| {
+            let (columns, values) = self.row(i);
+            let dot = columns.iter().zip(values).map(|(&column, &value)| value * vector[column]).sum::<T>();
+            self.norms[i] + norm - (dot + dot)
+        }).collect());
+    }
+
+    fn extend(&mut self, _: ArrayView2<'_, T>) -> Result<(), VoronoiError> {
+        return Err(VoronoiError::CanNotExtend);
+    }
+
+    fn truncate(&mut self, n_points: usize) {
+        // `extend` always fails, so there are never points to remove
+        debug_assert_eq!(n_points, self.n_points());
+    }
+
+    fn points(&self) -> Option<ArrayView2<'_, T>> {
+        None
+    }
+}
+
+/// Euclidean distances between points stored with a 16-bit floating point
+/// type (`half::f16` or `half::bf16`), accumulating the distances in `f32`
+/// to keep the precision of the sum
+#[cfg(feature = "half")]
+#[derive(Debug)]
+pub(crate) struct HalfDistances<'a, H> {
+    points: CowArray<'a, H, Ix2>,
+}
+
+#[cfg(feature = "half")]
+impl<'a, H> HalfDistances<'a, H> {
+    pub fn new(points: CowArray<'a, H, Ix2>) -> HalfDistances<'a, H> {
+        HalfDistances {
+            points: points,
+        }
+    }
+}
+
+#[cfg(feature = "half")]
+impl<'a, H> Distances<f32> for HalfDistances<'a, H> where H: Copy + Into<f32> + Send + Sync + std::fmt::Debug {
+    fn n_points(&self) -> usize {
+        self.points.nrows()
+    }
+
+    fn distance2(&self, i: usize, j: usize) -> f32 {
+        let mut distance2 = 0.0;
+        for (&a, &b) in self.points.row(i).iter().zip(self.points.row(j)) {
+            let diff = a.into() - b.into();
+            distance2 += diff * diff;
+        }
+        return distance2;
+    }
+
+    fn distances2_to_vector(&self, centers: &[usize], vector: ArrayView1<'_, f32>) -> Result<Vec<f32>, VoronoiError> {
+        check_features(&self.points, vector)?;
+        return Ok(centers.iter().map(|&i| {
+            let mut distance2 = 0.0;
+            for (&a, &b) in self.points.row(i).iter().zip(vector) {
+                let diff = a.into() - b;
+                distance2 += diff * diff;
+            }
+            distance2
+        }).collect());
+    }
+
+    fn extend(&mut self, _: ArrayView2<'_, f32>) -> Result<(), VoronoiError> {
+        return Err(VoronoiError::CanNotExtend);
+    }
+
+    fn truncate(&mut self, n_points: usize) {
+        // `extend` always fails, so there are never points to remove
+        debug_assert_eq!(n_points, self.n_points());
+    }
+
+    fn points(&self) -> Option<ArrayView2<'_, f32>> {
+        None
+    }
+}
@@ -0,0 +1,161 @@
+/// Possible errors when creating or using a `VoronoiDecomposer`
+#[derive(Debug, Clone, PartialEq)]
+pub enum VoronoiError {
+    /// The points do not have the expected number of dimensions
+    InvalidDimension {
+        expected: usize,
+        got: usize,
+    },
+    /// A point index is larger than the number of points
+    IndexOutOfBounds {
+        index: usize,
+        n_points: usize,
+    },
+    /// The initial point is also one of the excluded points
+    ExcludedInitial {
+        index: usize,
+    },
+    /// The point at this index is already selected
+    AlreadySelected {
+        index: usize,
+    },
+    /// The list of already selected points is empty
+    EmptySelection,
+    /// There are no points to select from
+    EmptyDataset,
+    /// The distance between the point at this index and a selected point is
+    /// NaN, for example because the point contains NaN values, or because the
+    /// squared norm of the points overflows with the Euclidean distance
+    NaNDistance {
+        index: usize,
+    },
+    /// The squared distance between the points at indexes `i` and `j` is
+    /// infinite, for example because it overflows the floating point type.
+    /// Infinite distances would always be the largest radius and break the
+    /// selection, so they are reported as errors instead of being clamped.
+    NonFiniteDistance {
+        i: usize,
+        j: usize,
+    },
+    /// A saved state does not match the number of points it is restored with
+    StateMismatch {
+        expected: usize,
+        got: usize,
+    },
+    /// A saved state can not be restored, because it was created by an
+    /// unsupported decomposer or because its cells are inconsistent
+    InvalidState {
+        message: String,
+    },
+    /// The point at this index is the zero vector, which has no direction
+    ZeroVector {
+        index: usize,
+    },
+    /// The number of weights does not match the number of points
+    InvalidWeights {
+        expected: usize,
+        got: usize,
+    },
+    /// The weight at this index (of a point, or of a view for multi-view
+    /// decomposers) is negative or NaN
+    NegativeWeight {
+        index: usize,
+    },
+    /// The view at this index does not have the same number of points as the
+    /// first view
+    InvalidView {
+        view: usize,
+        expected: usize,
+        got: usize,
+    },
+    /// The inverse covariance matrix is not positive-definite
+    NotPositiveDefinite,
+    /// New points can not be added to this decomposer
+    CanNotExtend,
+    /// Distances to new vectors can not be computed for this decomposer
+    UnsupportedQuery,
+    /// Random projections can only be used with the Euclidean distance
+    UnsupportedProjection,
+    /// The periodic cell matrix can not be inverted
+    SingularCell,
+    /// The periodic cell is too skewed to find the minimum image in the
+    /// neighboring cells
+    SkewedCell,
+    /// The kernel (or inverse covariance) matrix is not square
+    NotSquare {
+        rows: usize,
+        columns: usize,
+    },
+    /// The arrays describing a sparse matrix in CSR format are inconsistent
+    InvalidSparseMatrix {
+        message: String,
+    },
+    /// The kernel (or inverse covariance) matrix is not symmetric, `K(i, j)`
+    /// is different from `K(j, i)`
+    NotSymmetric {
+        i: usize,
+        j: usize,
+    },
+}
+
+impl std::fmt::Display for VoronoiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VoronoiError::InvalidDimension { expected, got } => {
+                write!(f, "expected points with {} dimensions, got {} dimensions", expected, got)
+            }
+            VoronoiError::IndexOutOfBounds { index, n_points } => {
+                write!(f, "index {} out of range for {} points", index, n_points)
+            }
+            VoronoiError::ExcludedInitial { index } => {
+                write!(f, "the initial point {} can not be excluded from the selection", index)
+            }
+            VoronoiError::AlreadySelected { index } => {
+                write!(f, "the point at index {} is already selected", index)
+            }
+            VoronoiError::EmptySelection => write!(f, "the list of selected points is empty"),
+            VoronoiError::EmptyDataset => write!(f, "can not select points from an empty dataset"),
+            VoronoiError::NaNDistance { index } => {
+                write!(f, "got a NaN distance for the point at index {}", index)
+            }
+            VoronoiError::NonFiniteDistance { i, j } => {
+                write!(f, "the distance between the points at index {} and {} is infinite", i, j)
+            }
+            VoronoiError::StateMismatch { expected, got } => {
+                write!(f, "the saved state was created with {} points, got {} points", expected, got)
+            }
+            VoronoiError::InvalidState { message } => write!(f, "invalid saved state: {}", message),
+            VoronoiError::ZeroVector { index } => {
+                write!(f, "the point at index {} is the zero vector", index)
+            }
+            VoronoiError::InvalidWeights { expected, got } => {
+                write!(f, "expected {} weights, got {}", expected, got)
+            }
+            VoronoiError::NegativeWeight { index } => {
+                write!(f, "the weight at index {} is negative", index)
+            }
+            VoronoiError::InvalidView { view, expected, got } => {
+                write!(f, "expected {} points in view {}, got {} points", expected, view, got)
+            }
+            VoronoiError::NotPositiveDefinite => write!(f, "the inverse covariance matrix is not positive-definite"),
+            VoronoiError::InvalidSparseMatrix { message } => write!(f, "invalid sparse matrix: {}", message),
+            VoronoiError::UnsupportedQuery => {
+                write!(f, "distances to new vectors are not available for this decomposer")
+            }
+            VoronoiError::UnsupportedProjection => {
+                write!(f, "random projections can only be used with the Euclidean distance")
+            }
+            VoronoiError::CanNotExtend => write!(f, "this decomposer can not be extended with new points"),
+            VoronoiError::SingularCell => write!(f, "the periodic cell matrix is singular"),
+            VoronoiError::SkewedCell => write!(f, "the periodic cell is too skewed"),
+            VoronoiError::NotSquare { rows, columns } => {
+                write!(f, "expected a square matrix, got a {}x{} matrix", rows, columns)
+            }
+            VoronoiError::NotSymmetric { i, j } => {
+                write!(f, "the matrix is not symmetric: M({0}, {1}) != M({1}, {0})", i, j)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VoronoiError {}
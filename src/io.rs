@@ -0,0 +1,636 @@
+//! Reading points and writing selected indexes, in `.npy` or `.csv` format.
+//! The format is determined from the file extension.
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+use ndarray::{Array1, Array2, ArrayView2};
+use ndarray_npy::ViewNpyExt;
+
+/// Check if the file at `path` has a `.csv` extension
+fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+}
+
+/// Split a path like `data.h5:/group/dataset` into the path to the HDF5 file
+/// and the name of the dataset inside this file. This returns `None` if the
+/// path does not refer to a dataset in a `.h5` or `.hdf5` file.
+fn split_hdf5_path(path: &Path) -> Option<(&Path, &str)> {
+    let path = path.to_str()?;
+    for extension in &[".h5:", ".hdf5:"] {
+        if let Some(position) = path.find(extension) {
+            let split = position + extension.len() - 1;
+            return Some((Path::new(&path[..split]), &path[split + 1..]));
+        }
+    }
+    return None;
+}
+
+/// Read points from the file at `path`, either from a 2D `.npy` array, or
+/// from a comma-separated `.csv` file with one point per line and an optional
+/// header row. The first line is only used as a header if none of its values
+/// are numbers.
+///
+/// `.npy` arrays can contain `f64`, `u8`, `i16` or `i32` values, integers
+/// being converted to `f64` after reading.
+///
+/// With the `hdf5` feature, this can also read a 2D dataset from an HDF5
+/// file, using `file.h5:/path/to/dataset` as `path`.
+pub fn read_points<P: AsRef<Path>>(path: P) -> Result<Array2<f64>, Box<dyn Error>> {
+    let path = path.as_ref();
+    if let Some((file, dataset)) = split_hdf5_path(path) {
+        return read_hdf5(file, dataset);
+    } else if is_csv(path) {
+        return read_csv(path);
+    } else {
+        return read_npy_points(path);
+    }
+}
+
+/// Types of `.npy` arrays that can be read by `read_points`, as numpy type
+/// strings
+pub const NPY_POINTS_TYPES: [&str; 4] = ["<f8", "|u1", "<i2", "<i4"];
+
+fn read_npy_points(path: &Path) -> Result<Array2<f64>, Box<dyn Error>> {
+    let header = read_npy_header(path)?;
+    return match header.descr.as_str() {
+        "<f8" => Ok(ndarray_npy::read_npy(path)?),
+        "|u1" => Ok(ndarray_npy::read_npy::<_, Array2<u8>>(path)?.mapv(f64::from)),
+        "<i2" => Ok(ndarray_npy::read_npy::<_, Array2<i16>>(path)?.mapv(f64::from)),
+        "<i4" => Ok(ndarray_npy::read_npy::<_, Array2<i32>>(path)?.mapv(f64::from)),
+        descr => Err(format!(
+            "unsupported type '{}' in {}, expected one of {:?}", descr, path.display(), NPY_POINTS_TYPES
+        ).into()),
+    };
+}
+
+#[cfg(feature = "hdf5")]
+fn read_hdf5(path: &Path, dataset: &str) -> Result<Array2<f64>, Box<dyn Error>> {
+    let file = hdf5::File::open(path)?;
+    return Ok(file.dataset(dataset)?.read_2d::<f64>()?);
+}
+
+#[cfg(not(feature = "hdf5"))]
+fn read_hdf5(path: &Path, _: &str) -> Result<Array2<f64>, Box<dyn Error>> {
+    return Err(format!("can not read {}: HDF5 support requires the `hdf5` feature", path.display()).into());
+}
+
+fn read_csv(path: &Path) -> Result<Array2<f64>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+
+    let mut n_columns = None;
+    let mut data = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record?;
+
+        let values = record.iter().map(str::parse::<f64>).collect::<Result<Vec<_>, _>>();
+        let values = match values {
+            Ok(values) => values,
+            // the first line is allowed to be a header, if none of its
+            // fields are numbers
+            Err(_) if line == 0 && record.iter().all(|field| field.parse::<f64>().is_err()) => continue,
+            Err(e) => return Err(format!("invalid value on line {} of {}: {}", line + 1, path.display(), e).into()),
+        };
+
+        match n_columns {
+            None => n_columns = Some(values.len()),
+            Some(n_columns) => if values.len() != n_columns {
+                return Err(format!(
+                    "expected {} values on line {} of {}, got {}",
+                    n_columns, line + 1, path.display(), values.len()
+                ).into());
+            }
+        }
+
+        data.extend(values);
+    }
+
+    let n_columns = n_columns.unwrap_or(0);
+    let n_rows = data.len().checked_div(n_columns).unwrap_or(0);
+    return Ok(Array2::from_shape_vec((n_rows, n_columns), data)?);
+}
+
+/// Information from the header of a `.npy` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpyHeader {
+    /// Type of the data, as a numpy type string (e.g. `<f8`)
+    pub descr: String,
+    /// Is the data stored in Fortran (column-major) order?
+    pub fortran_order: bool,
+    /// Shape of the stored array
+    pub shape: Vec<usize>,
+}
+
+/// Read the header of the `.npy` file at `path`, without reading the data.
+/// This gives the shape and type of the array, to validate inputs before
+/// loading them.
+pub fn read_npy_header<P: AsRef<Path>>(path: P) -> Result<NpyHeader, Box<dyn Error>> {
+    use std::io::Read;
+
+    let path = path.as_ref();
+    let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+
+    let mut start = [0; 8];
+    file.read_exact(&mut start)?;
+    if &start[..6] != b"\x93NUMPY" {
+        return Err(format!("{} is not a .npy file", path.display()).into());
+    }
+
+    let header_len = match start[6] {
+        1 => {
+            let mut length = [0; 2];
+            file.read_exact(&mut length)?;
+            u16::from_le_bytes(length) as usize
+        }
+        2 | 3 => {
+            let mut length = [0; 4];
+            file.read_exact(&mut length)?;
+            u32::from_le_bytes(length) as usize
+        }
+        version => return Err(format!("unsupported .npy version {} in {}", version, path.display()).into()),
+    };
+
+    let mut header = vec![0; header_len];
+    file.read_exact(&mut header)?;
+    let header = String::from_utf8(header)?;
+
+    let invalid = || format!("invalid .npy header in {}: {}", path.display(), header.trim());
+    let value = |key: &str| -> Result<&str, String> {
+        let start = header.find(&format!("'{}':", key)).ok_or_else(invalid)? + key.len() + 3;
+        return Ok(header[start..].trim_start());
+    };
+
+    let descr = value("descr")?;
+    let descr = descr.strip_prefix('\'').and_then(|d| d.split('\'').next()).ok_or_else(invalid)?;
+
+    let fortran_order = value("fortran_order")?.starts_with("True");
+
+    let shape = value("shape")?;
+    let shape = shape.strip_prefix('(').and_then(|s| s.split(')').next()).ok_or_else(invalid)?;
+    let shape = shape.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    return Ok(NpyHeader {
+        descr: descr.into(),
+        fortran_order: fortran_order,
+        shape: shape,
+    });
+}
+
+/// Points stored in a memory-mapped `.npy` file, for datasets too large to
+/// fit in memory. The operating system loads the rows from disk as they are
+/// accessed. The view can be given directly to a `VoronoiDecomposer`:
+///
+/// ```no_run
+/// # use farthest_sampler::VoronoiDecomposer;
+/// # use farthest_sampler::io::MappedPoints;
+/// let mapped = MappedPoints::open("points.npy").unwrap();
+/// let mut voronoi = VoronoiDecomposer::new(mapped.view().unwrap().into(), 0);
+/// voronoi.select(100);
+/// ```
+///
+/// When running the selection, the points are accessed in increasing index
+/// order inside each Voronoï cell: a first sequential pass over all points
+/// when creating the decomposer, and then one pass over the points of the
+/// cells close to each new selected point.
+pub struct MappedPoints {
+    mmap: memmap2::Mmap,
+}
+
+impl MappedPoints {
+    /// Map the `.npy` file at `path` in memory. The file must contain a 2D
+    /// array of little-endian `f64` in C (row-major) order.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MappedPoints, Box<dyn Error>> {
+        let file = std::fs::File::open(path)?;
+        // Safety: the file must not be modified while it is mapped, which we
+        // can not guarantee. This is the same contract as any other use of
+        // memory-mapped files.
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+
+        let points = MappedPoints { mmap: mmap };
+        // check that the header and data are valid right away
+        points.view()?;
+
+        return Ok(points);
+    }
+
+    /// Get a view of the points in this file
+    pub fn view(&self) -> Result<ArrayView2<'_, f64>, Box<dyn Error>> {
+        return Ok(ArrayView2::<f64>::view_npy(&self.mmap)?);
+    }
+}
+
+/// Read indexes of selected points from the file at `path`, either from a 1D
+/// `.npy` array of integers, or from a one-column `.csv` file.
+pub fn read_indices<P: AsRef<Path>>(path: P) -> Result<Vec<usize>, Box<dyn Error>> {
+    let path = path.as_ref();
+    if is_csv(path) {
+        let points = read_csv(path)?;
+        if points.ncols() != 1 {
+            return Err(format!("expected a single column in {}, got {}", path.display(), points.ncols()).into());
+        }
+
+        return points.iter().map(|&index| {
+            if index >= 0.0 && index.fract() == 0.0 {
+                Ok(index as usize)
+            } else {
+                Err(format!("invalid index {} in {}", index, path.display()).into())
+            }
+        }).collect();
+    }
+
+    if let Ok(indices) = ndarray_npy::read_npy::<_, Array1<u64>>(path) {
+        return Ok(indices.iter().map(|&i| i as usize).collect());
+    }
+
+    if let Ok(indices) = ndarray_npy::read_npy::<_, Array1<u32>>(path) {
+        return Ok(indices.iter().map(|&i| i as usize).collect());
+    }
+
+    // numpy uses signed integers by default
+    let indices: Array1<i64> = ndarray_npy::read_npy(path)?;
+    return indices.iter().map(|&index| {
+        usize::try_from(index).map_err(|_| format!("invalid index {} in {}", index, path.display()).into())
+    }).collect();
+}
+
+/// Integer type used to store indices in `.npy` files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexType {
+    /// 32-bit unsigned integers, for up to 2^32 points
+    U32,
+    /// 64-bit unsigned integers
+    U64,
+}
+
+impl std::str::FromStr for IndexType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<IndexType, String> {
+        match value {
+            "u32" => Ok(IndexType::U32),
+            "u64" => Ok(IndexType::U64),
+            _ => Err(format!("invalid index type '{}', expected 'u32' or 'u64'", value)),
+        }
+    }
+}
+
+/// Write the selected `indices` to the file at `path`, either as a 1D `.npy`
+/// array of 64-bit unsigned integers, or as a one-column `.csv` file.
+pub fn write_indices<P: AsRef<Path>>(path: P, indices: &[usize]) -> Result<(), Box<dyn Error>> {
+    return write_indices_as(path, indices, IndexType::U64);
+}
+
+/// Write the selected `indices` to the file at `path`, either as a 1D `.npy`
+/// array of integers of the given type, or as a one-column `.csv` file. This
+/// returns an error if one of the indices does not fit in `index_type`.
+pub fn write_indices_as<P: AsRef<Path>>(path: P, indices: &[usize], index_type: IndexType) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    if is_csv(path) {
+        let mut writer = csv::Writer::from_path(path)?;
+        for index in indices {
+            writer.write_record(&[index.to_string()])?;
+        }
+        writer.flush()?;
+    } else {
+        match index_type {
+            IndexType::U32 => {
+                let indices = indices.iter()
+                    .map(|&i| u32::try_from(i).map_err(|_| format!("index {} is too large to be stored as u32", i)))
+                    .collect::<Result<Array1<_>, _>>()?;
+                ndarray_npy::write_npy(path, &indices)?;
+            }
+            IndexType::U64 => {
+                let indices = indices.iter().map(|&i| i as u64).collect::<Array1<_>>();
+                ndarray_npy::write_npy(path, &indices)?;
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+/// Description of a selection, stored in the archives written by
+/// `write_archive`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectionMetadata {
+    /// Shape of the dataset, as `(n_points, n_features)`
+    pub shape: (usize, usize),
+    /// Name of the metric used to compute distances
+    pub metric: String,
+    /// Index of the first selected point
+    pub initial: usize,
+}
+
+/// Write the results of a selection to a single `.npz` archive at `path`.
+///
+/// The archive contains the selected `indices`, the `radius` that triggered
+/// the selection of each point and the `assignments` of all points to the
+/// Voronoï cells, with the indices stored as `index_type`. The metadata is
+/// stored in the `shape` (`u64`), `initial` (0-dimensional `u64`), `metric`
+/// and `version` (the version of this crate) arrays, the last two as UTF-8
+/// bytes to be decoded with `bytes(archive["metric"]).decode()`.
+pub fn write_archive<P: AsRef<Path>>(
+    path: P,
+    indices: &[usize],
+    radius: &[f64],
+    assignments: &[usize],
+    index_type: IndexType,
+    metadata: &SelectionMetadata,
+) -> Result<(), Box<dyn Error>> {
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut npz = ndarray_npy::NpzWriter::new(file);
+
+    for &(name, values) in &[("indices.npy", indices), ("assignments.npy", assignments)] {
+        match index_type {
+            IndexType::U32 => {
+                let values = values.iter()
+                    .map(|&i| u32::try_from(i).map_err(|_| format!("index {} is too large to be stored as u32", i)))
+                    .collect::<Result<Array1<_>, _>>()?;
+                npz.add_array(name, &values)?;
+            }
+            IndexType::U64 => {
+                npz.add_array(name, &values.iter().map(|&i| i as u64).collect::<Array1<_>>())?;
+            }
+        }
+    }
+    npz.add_array("radius.npy", &Array1::from(radius.to_vec()))?;
+
+    npz.add_array("shape.npy", &ndarray::arr1(&[metadata.shape.0 as u64, metadata.shape.1 as u64]))?;
+    npz.add_array("initial.npy", &ndarray::arr0(metadata.initial as u64))?;
+    npz.add_array("metric.npy", &Array1::from(metadata.metric.as_bytes().to_vec()))?;
+    npz.add_array("version.npy", &Array1::from(env!("CARGO_PKG_VERSION").as_bytes().to_vec()))?;
+    npz.finish()?;
+
+    return Ok(());
+}
+
+/// Total size of the header written by `NpyStreamWriter`, leaving enough
+/// space to store any `u64` number of values
+const STREAM_HEADER_SIZE: usize = 128;
+
+/// Writer for a 1D `.npy` array of `f64`, appending values one at a time
+/// instead of storing all of them in memory.
+///
+/// The file is a standard `.npy` file (version 1.0), with a header padded to
+/// 128 bytes followed by the values in little-endian order. Every `flush_every`
+/// values, the pending values are written to disk and the shape in the header
+/// is updated, so the file can be read with `numpy.load` at any point, and
+/// contains all the values up to the last flush.
+pub struct NpyStreamWriter {
+    file: std::io::BufWriter<std::fs::File>,
+    /// Number of values written to the file so far
+    count: u64,
+    /// Number of values written since the last flush
+    pending: usize,
+    flush_every: usize,
+}
+
+impl NpyStreamWriter {
+    /// Create a new stream writer at `path`, flushing the values to disk every
+    /// `flush_every` values
+    pub fn create<P: AsRef<Path>>(path: P, flush_every: usize) -> Result<NpyStreamWriter, Box<dyn Error>> {
+        let file = std::fs::File::create(path)?;
+        let mut writer = NpyStreamWriter {
+            file: std::io::BufWriter::new(file),
+            count: 0,
+            pending: 0,
+            flush_every: flush_every.max(1),
+        };
+        writer.write_header()?;
+        return Ok(writer);
+    }
+
+    /// Append a single `value` to the file
+    pub fn push(&mut self, value: f64) -> Result<(), Box<dyn Error>> {
+        self.file.write_all(&value.to_le_bytes())?;
+        self.count += 1;
+        self.pending += 1;
+        if self.pending >= self.flush_every {
+            self.flush()?;
+        }
+        return Ok(());
+    }
+
+    /// Write all pending values to disk, and update the header accordingly
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.file.flush()?;
+        self.write_header()?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.pending = 0;
+        return Ok(());
+    }
+
+    /// Flush all remaining values and close the file
+    pub fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+        self.file.get_ref().sync_all()?;
+        return Ok(());
+    }
+
+    fn write_header(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut dict = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': ({},), }}", self.count);
+        // 6 bytes of magic string, 2 bytes of version, 2 bytes of header
+        // length, and the dictionary ending with a newline
+        let header_len = STREAM_HEADER_SIZE - 10;
+        while dict.len() < header_len - 1 {
+            dict.push(' ');
+        }
+        dict.push('\n');
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(b"\x93NUMPY\x01\x00")?;
+        self.file.write_all(&(header_len as u16).to_le_bytes())?;
+        self.file.write_all(dict.as_bytes())?;
+        self.file.flush()?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn temporary_path(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("farthest-sampler-{}-{}", std::process::id(), name));
+        return path;
+    }
+
+    #[test]
+    fn csv() {
+        let path = temporary_path("points.csv");
+        std::fs::write(&path, "x, y\n0.0, 1.0\n2.5, -3e2\n").unwrap();
+        let points = read_points(&path).unwrap();
+        assert_eq!(points, ndarray::arr2(&[[0.0, 1.0], [2.5, -300.0]]));
+
+        std::fs::write(&path, "0.0, 1.0\n2.5, -3e2\n").unwrap();
+        let points = read_points(&path).unwrap();
+        assert_eq!(points, ndarray::arr2(&[[0.0, 1.0], [2.5, -300.0]]));
+
+        std::fs::write(&path, "0.0, 1.0\n2.5\n").unwrap();
+        assert!(read_points(&path).is_err());
+
+        // a first line with some numbers is not a header
+        std::fs::write(&path, "1.0, abc\n0.0, 1.0\n").unwrap();
+        let error = read_points(&path).unwrap_err();
+        assert!(error.to_string().starts_with("invalid value on line 1"));
+
+        write_indices(&path, &[3, 1, 4]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "3\n1\n4\n");
+        assert_eq!(read_indices(&path).unwrap(), [3, 1, 4]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn hdf5_path() {
+        assert_eq!(split_hdf5_path(Path::new("data.h5:/descriptors")), Some((Path::new("data.h5"), "/descriptors")));
+        assert_eq!(split_hdf5_path(Path::new("dir/data.hdf5:/a/b")), Some((Path::new("dir/data.hdf5"), "/a/b")));
+        assert_eq!(split_hdf5_path(Path::new("data.h5")), None);
+        assert_eq!(split_hdf5_path(Path::new("data.npy")), None);
+    }
+
+    #[test]
+    fn npy_stream() {
+        let path = temporary_path("stream.npy");
+        let mut writer = NpyStreamWriter::create(&path, 100).unwrap();
+        for i in 0..1234 {
+            writer.push(i as f64 / 2.0).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let values: Array1<f64> = ndarray_npy::read_npy(&path).unwrap();
+        assert_eq!(values, Array1::from_shape_fn(1234, |i| i as f64 / 2.0));
+
+        let writer = NpyStreamWriter::create(&path, 100).unwrap();
+        writer.finish().unwrap();
+        let values: Array1<f64> = ndarray_npy::read_npy(&path).unwrap();
+        assert_eq!(values.len(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn npy_header() {
+        let path = temporary_path("header.npy");
+        ndarray_npy::write_npy(&path, &Array2::<f64>::zeros((12, 3))).unwrap();
+        let header = read_npy_header(&path).unwrap();
+        assert_eq!(header, NpyHeader { descr: "<f8".into(), fortran_order: false, shape: vec![12, 3] });
+
+        ndarray_npy::write_npy(&path, &ndarray::arr1(&[3u32, 4])).unwrap();
+        let header = read_npy_header(&path).unwrap();
+        assert_eq!(header, NpyHeader { descr: "<u4".into(), fortran_order: false, shape: vec![2] });
+
+        std::fs::write(&path, "0.0, 1.0\n").unwrap();
+        assert!(read_npy_header(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn npy_integers() {
+        let path = temporary_path("integers.npy");
+        let expected = ndarray::arr2(&[[0.0, 1.0, 255.0], [2.0, 3.0, 4.0]]);
+
+        ndarray_npy::write_npy(&path, &expected.mapv(|v| v as u8)).unwrap();
+        assert_eq!(read_points(&path).unwrap(), expected);
+
+        ndarray_npy::write_npy(&path, &expected.mapv(|v| -v as i16)).unwrap();
+        assert_eq!(read_points(&path).unwrap(), -&expected);
+
+        ndarray_npy::write_npy(&path, &expected.mapv(|v| v as i32)).unwrap();
+        assert_eq!(read_points(&path).unwrap(), expected);
+
+        ndarray_npy::write_npy(&path, &expected.mapv(|v| v as u64)).unwrap();
+        assert!(read_points(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn archive() {
+        let path = temporary_path("selection.npz");
+        let metadata = SelectionMetadata {
+            shape: (5, 3),
+            metric: "euclidean".into(),
+            initial: 2,
+        };
+        write_archive(&path, &[2, 4], &[f64::INFINITY, 1.5], &[0, 1, 0, 0, 1], IndexType::U32, &metadata).unwrap();
+
+        let mut npz = ndarray_npy::NpzReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let indices: Array1<u32> = npz.by_name("indices.npy").unwrap();
+        assert_eq!(indices, ndarray::arr1(&[2, 4]));
+        let assignments: Array1<u32> = npz.by_name("assignments.npy").unwrap();
+        assert_eq!(assignments, ndarray::arr1(&[0, 1, 0, 0, 1]));
+        let radius: Array1<f64> = npz.by_name("radius.npy").unwrap();
+        assert_eq!(radius, ndarray::arr1(&[f64::INFINITY, 1.5]));
+
+        let shape: Array1<u64> = npz.by_name("shape.npy").unwrap();
+        assert_eq!(shape, ndarray::arr1(&[5, 3]));
+        let initial: ndarray::Array0<u64> = npz.by_name("initial.npy").unwrap();
+        assert_eq!(initial, ndarray::arr0(2));
+        let metric: Array1<u8> = npz.by_name("metric.npy").unwrap();
+        assert_eq!(metric.as_slice().unwrap(), b"euclidean");
+        let version: Array1<u8> = npz.by_name("version.npy").unwrap();
+        assert_eq!(version.as_slice().unwrap(), env!("CARGO_PKG_VERSION").as_bytes());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn mmap() {
+        let path = temporary_path("points.npy");
+        let points = ndarray::arr2(&[[0.0, 1.0, 2.0], [2.5, -300.0, 4.0]]);
+        ndarray_npy::write_npy(&path, &points).unwrap();
+
+        let mapped = MappedPoints::open(&path).unwrap();
+        assert_eq!(mapped.view().unwrap(), points);
+
+        ndarray_npy::write_npy(&path, &ndarray::arr1(&[1.0, 2.0])).unwrap();
+        assert!(MappedPoints::open(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn npy() {
+        let path = temporary_path("selected.npy");
+        write_indices(&path, &[3, 1, 4]).unwrap();
+        let indices: Array1<u64> = ndarray_npy::read_npy(&path).unwrap();
+        assert_eq!(indices, ndarray::arr1(&[3, 1, 4]));
+        assert_eq!(read_indices(&path).unwrap(), [3, 1, 4]);
+
+        ndarray_npy::write_npy(&path, &ndarray::arr1(&[5i64, 9])).unwrap();
+        assert_eq!(read_indices(&path).unwrap(), [5, 9]);
+
+        ndarray_npy::write_npy(&path, &ndarray::arr1(&[5i64, -9])).unwrap();
+        assert!(read_indices(&path).is_err());
+
+        write_indices_as(&path, &[3, 1, 4], IndexType::U32).unwrap();
+        let indices: Array1<u32> = ndarray_npy::read_npy(&path).unwrap();
+        assert_eq!(indices, ndarray::arr1(&[3, 1, 4]));
+        assert_eq!(read_indices(&path).unwrap(), [3, 1, 4]);
+
+        if let Ok(large) = usize::try_from(u64::from(u32::MAX) + 1) {
+            assert!(write_indices_as(&path, &[large], IndexType::U32).is_err());
+            write_indices_as(&path, &[large], IndexType::U64).unwrap();
+            assert_eq!(read_indices(&path).unwrap(), [large]);
+        }
+
+        assert_eq!("u32".parse(), Ok(IndexType::U32));
+        assert!("i32".parse::<IndexType>().is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,17 +1,15 @@
 #![allow(clippy::needless_return, clippy::redundant_field_names)]
 
-#[cfg(features = "time-graph")]
+#[cfg(feature = "time-graph")]
 macro_rules! tracing_span {
     ($name: literal, $code: tt) => {
         {
-            let span = tracing::info_span!($name);
-            let _guard = span.enter();
-            $code
+            time_graph::spanned!($name, $code)
         }
     };
 }
 
-#[cfg(not(features = "time-graph"))]
+#[cfg(not(feature = "time-graph"))]
 macro_rules! tracing_span {
     ($name: literal, $code: tt) => {
         {
@@ -20,20 +18,126 @@ macro_rules! tracing_span {
     };
 }
 
+/// Emit a debug message with the `log` crate if the `log` feature is enabled.
+/// Without it, the arguments are not evaluated.
+#[cfg(feature = "log")]
+macro_rules! log_debug {
+    ($($args: tt)*) => { log::debug!($($args)*) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_debug {
+    ($($args: tt)*) => {};
+}
+
+/// Same as `log_debug!`, for trace messages. The arguments are only
+/// evaluated if trace messages are enabled in the logger.
+#[cfg(feature = "log")]
+macro_rules! log_trace {
+    ($($args: tt)*) => { log::trace!($($args)*) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_trace {
+    ($($args: tt)*) => {};
+}
+
+/// Iterate over a collection in parallel if the `rayon` feature is enabled,
+/// and serially otherwise
+#[cfg(feature = "rayon")]
+macro_rules! par_iter {
+    ($collection: expr) => { $collection.par_iter() };
+}
+
+#[cfg(not(feature = "rayon"))]
+macro_rules! par_iter {
+    ($collection: expr) => { $collection.iter() };
+}
+
+#[cfg(feature = "rayon")]
+macro_rules! into_par_iter {
+    ($collection: expr) => { $collection.into_par_iter() };
+}
+
+#[cfg(not(feature = "rayon"))]
+macro_rules! into_par_iter {
+    ($collection: expr) => { $collection.into_iter() };
+}
+
+#[cfg(feature = "rayon")]
+macro_rules! par_chunks {
+    ($collection: expr, $size: expr) => { $collection.par_chunks($size) };
+}
+
+#[cfg(not(feature = "rayon"))]
+macro_rules! par_chunks {
+    ($collection: expr, $size: expr) => { $collection.chunks($size) };
+}
+
+/// Floating point types that can be used for the points and distances in
+/// Farthest Points Sampling, i.e. `f32` and `f64`.
+pub trait Float: num_traits::Float + ndarray::LinalgScalar + std::iter::Sum + Default + std::fmt::Debug + Send + Sync {}
+
+impl<T> Float for T where T: num_traits::Float + ndarray::LinalgScalar + std::iter::Sum + Default + std::fmt::Debug + Send + Sync {}
+
+/// Reduce the items of a parallel iterator (or a serial one without the
+/// `rayon` feature) with the given function, returning `None` if there are
+/// no items
+#[cfg(feature = "rayon")]
+macro_rules! reduce_with {
+    ($iterator: expr, $function: expr) => { $iterator.reduce_with($function) };
+}
+
+#[cfg(not(feature = "rayon"))]
+macro_rules! reduce_with {
+    ($iterator: expr, $function: expr) => { $iterator.reduce($function) };
+}
+
 /// Get both the maximal value in `values` and the position of this maximal
-/// value
-pub fn find_max<'a, I: Iterator<Item=&'a f64>>(values: I) -> (usize, f64) {
+/// value. If multiple values are equal to the maximum, this returns the
+/// first one.
+pub fn find_max<'a, T: PartialOrd + Copy + 'a, I: Iterator<Item=&'a T>>(values: I) -> (usize, T) {
     values
         .enumerate()
-        .max_by(|(_, a), (_, b)| a.partial_cmp(b).expect("got NaN value"))
+        // for equal values, the lowest index compares as the largest
+        .max_by(|(i, a), (j, b)| a.partial_cmp(b).expect("got NaN value").then(j.cmp(i)))
         .map(|(index, value)| (index, *value))
         .expect("got an empty slice")
 }
 
+mod error;
+pub use error::VoronoiError;
+
+pub mod metric;
+pub use metric::Metric;
+
+mod distances;
+
+#[cfg(feature = "io")]
+pub mod io;
+
 pub mod simple;
+pub use simple::fps_bruteforce;
 
 pub mod voronoi;
-pub use voronoi::VoronoiDecomposer;
+pub use voronoi::{deduplicate, AddPointEvent, CellInfo, CellsSnapshot, Coverage, Selections, VoronoiDecomposer};
+#[cfg(feature = "serde")]
+pub use voronoi::VoronoiState;
+#[cfg(feature = "metrics")]
+pub use voronoi::Timings;
+
+mod builder;
+pub use builder::VoronoiDecomposerBuilder;
+
+mod sharded;
+pub use sharded::ShardedSelector;
+
+/// `VoronoiDecomposer` using `f64` for the points and distances
+pub type VoronoiDecomposerF64<'a> = VoronoiDecomposer<'a, f64>;
 
 #[cfg(feature = "python")]
 mod python;
+
+#[cfg(feature = "capi")]
+#[allow(non_camel_case_types)]
+pub mod capi;
@@ -0,0 +1,459 @@
+//! Distance metrics usable with a `VoronoiDecomposer`.
+//!
+//! The Voronoï decomposition uses the triangle inequality to skip distance
+//! computations, so the square root of `Metric::distance2` must be a proper
+//! metric (or at least a pseudo-metric) for the selection to be exact. This is
+//! the case for all the metrics defined in this module.
+
+use ndarray::{Array2, ArrayView1, ArrayView2};
+
+use crate::{Float, VoronoiError};
+
+/// A distance between points, used to run Farthest Points Sampling on points
+/// with coordinates of type `T`.
+pub trait Metric<T: Float = f64>: Send + Sync {
+    /// Get the squared distance between `a` and `b`. The square root of this
+    /// value must follow the triangle inequality.
+    fn distance2(&self, a: ArrayView1<'_, T>, b: ArrayView1<'_, T>) -> T;
+
+    /// Get the number of dimensions the points must have to use this metric,
+    /// or `None` if the metric works with any number of dimensions
+    fn dimension(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl<T: Float, M: Metric<T> + ?Sized> Metric<T> for Box<M> {
+    fn distance2(&self, a: ArrayView1<'_, T>, b: ArrayView1<'_, T>) -> T {
+        (**self).distance2(a, b)
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        (**self).dimension()
+    }
+}
+
+/// Standard Euclidean distance
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Euclidean;
+
+impl<T: Float> Metric<T> for Euclidean {
+    fn distance2(&self, a: ArrayView1<'_, T>, b: ArrayView1<'_, T>) -> T {
+        a.iter().zip(b).map(|(&a, &b)| (a - b) * (a - b)).sum()
+    }
+}
+
+/// Euclidean distance, using Kahan's compensated summation to accumulate the
+/// squared differences between coordinates.
+///
+/// This is slower than [`Euclidean`], but much more accurate for points with
+/// many dimensions, or with coordinates of very different magnitudes,
+/// especially with `f32` points.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StableEuclidean;
+
+impl<T: Float> Metric<T> for StableEuclidean {
+    fn distance2(&self, a: ArrayView1<'_, T>, b: ArrayView1<'_, T>) -> T {
+        let mut sum = T::zero();
+        let mut compensation = T::zero();
+        for (&a, &b) in a.iter().zip(b) {
+            let y = (a - b) * (a - b) - compensation;
+            let t = sum + y;
+            compensation = (t - sum) - y;
+            sum = t;
+        }
+        return sum;
+    }
+}
+
+/// Manhattan (or L1) distance, `d(a, b) = sum |a_i - b_i|`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Manhattan;
+
+impl<T: Float> Metric<T> for Manhattan {
+    fn distance2(&self, a: ArrayView1<'_, T>, b: ArrayView1<'_, T>) -> T {
+        let distance = a.iter().zip(b).map(|(&a, &b)| (a - b).abs()).sum::<T>();
+        return distance * distance;
+    }
+}
+
+/// Cosine distance, `d^2(a, b) = 2 (1 - cos θ)`, where θ is the angle between
+/// `a` and `b`.
+///
+/// This is the Euclidean distance between the normalized vectors, and is a
+/// valid metric on the directions of the vectors. The distance is not defined
+/// if one of the vectors is zero.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cosine;
+
+impl<T: Float> Metric<T> for Cosine {
+    fn distance2(&self, a: ArrayView1<'_, T>, b: ArrayView1<'_, T>) -> T {
+        let cos = a.dot(&b) / (a.dot(&a) * b.dot(&b)).sqrt();
+        let one_minus_cos = T::one() - cos;
+        return one_minus_cos + one_minus_cos;
+    }
+}
+
+/// Great-circle distance between points on a sphere, computed with the
+/// haversine formula. The points must have two coordinates, the latitude and
+/// longitude in degrees.
+///
+/// The great-circle distance is the geodesic distance on the sphere, and
+/// follows the triangle inequality, so the Voronoï pruning remains valid.
+#[derive(Debug, Clone, Copy)]
+pub struct Haversine {
+    /// Radius of the sphere, the distances are given in the same unit
+    pub radius: f64,
+}
+
+impl Haversine {
+    /// Mean radius of the Earth, in kilometers
+    pub const EARTH_RADIUS: f64 = 6371.0088;
+
+    /// Create a new haversine metric on a sphere with the given `radius`
+    pub fn new(radius: f64) -> Haversine {
+        Haversine {
+            radius: radius
+        }
+    }
+}
+
+impl Default for Haversine {
+    /// Haversine distance on the Earth, in kilometers
+    fn default() -> Haversine {
+        Haversine::new(Haversine::EARTH_RADIUS)
+    }
+}
+
+impl<T: Float> Metric<T> for Haversine {
+    fn distance2(&self, a: ArrayView1<'_, T>, b: ArrayView1<'_, T>) -> T {
+        let two = T::from(2.0).expect("failed to convert 2");
+        let radius = T::from(self.radius).expect("failed to convert radius");
+
+        let (latitude_a, longitude_a) = (a[0].to_radians(), a[1].to_radians());
+        let (latitude_b, longitude_b) = (b[0].to_radians(), b[1].to_radians());
+
+        let sin_latitude = ((latitude_b - latitude_a) / two).sin();
+        let sin_longitude = ((longitude_b - longitude_a) / two).sin();
+        let h = sin_latitude * sin_latitude + latitude_a.cos() * latitude_b.cos() * sin_longitude * sin_longitude;
+
+        // clamp h to 1 to protect against rounding errors for antipodal points
+        let distance = two * radius * h.min(T::one()).sqrt().asin();
+        return distance * distance;
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(2)
+    }
+}
+
+/// Mahalanobis distance, `d^2(a, b) = (a - b)^T Σ^-1 (a - b)`, where `Σ^-1`
+/// is the inverse of the covariance matrix of the features.
+///
+/// This is a proper metric as long as `Σ^-1` is symmetric and
+/// positive-definite, which is checked when creating the metric.
+#[derive(Debug, Clone)]
+pub struct Mahalanobis {
+    /// Lower triangular Cholesky factor `L` of the inverse covariance, with
+    /// `Σ^-1 = L L^T`
+    cholesky: Array2<f64>,
+}
+
+impl Mahalanobis {
+    /// Create a new Mahalanobis metric from the `inverse_covariance` matrix.
+    /// This returns an error if the matrix is not square, not symmetric, or
+    /// not positive-definite.
+    pub fn new(inverse_covariance: ArrayView2<'_, f64>) -> Result<Mahalanobis, VoronoiError> {
+        let n = inverse_covariance.nrows();
+        if inverse_covariance.ncols() != n {
+            return Err(VoronoiError::NotSquare { rows: n, columns: inverse_covariance.ncols() });
+        }
+
+        let tolerance = 100.0 * f64::EPSILON;
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let (mij, mji) = (inverse_covariance[[i, j]], inverse_covariance[[j, i]]);
+                if (mij - mji).abs() > tolerance * f64::max(mij.abs(), mji.abs()) {
+                    return Err(VoronoiError::NotSymmetric { i, j });
+                }
+            }
+        }
+
+        // Cholesky decomposition, which only succeeds for positive-definite
+        // matrices
+        let mut cholesky = Array2::zeros((n, n));
+        for j in 0..n {
+            let mut diagonal = inverse_covariance[[j, j]];
+            for k in 0..j {
+                diagonal -= cholesky[[j, k]] * cholesky[[j, k]];
+            }
+
+            if diagonal <= 0.0 || diagonal.is_nan() {
+                return Err(VoronoiError::NotPositiveDefinite);
+            }
+            cholesky[[j, j]] = diagonal.sqrt();
+
+            for i in (j + 1)..n {
+                let mut value = inverse_covariance[[i, j]];
+                for k in 0..j {
+                    value -= cholesky[[i, k]] * cholesky[[j, k]];
+                }
+                cholesky[[i, j]] = value / cholesky[[j, j]];
+            }
+        }
+
+        Ok(Mahalanobis {
+            cholesky: cholesky,
+        })
+    }
+}
+
+impl Metric<f64> for Mahalanobis {
+    fn distance2(&self, a: ArrayView1<'_, f64>, b: ArrayView1<'_, f64>) -> f64 {
+        let delta = &a - &b;
+        // (a - b)^T L L^T (a - b) = |L^T (a - b)|^2
+        let mut distance2 = 0.0;
+        for j in 0..delta.len() {
+            let mut y = 0.0;
+            for i in j..delta.len() {
+                y += self.cholesky[[i, j]] * delta[i];
+            }
+            distance2 += y * y;
+        }
+        return distance2;
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(self.cholesky.nrows())
+    }
+}
+
+/// Euclidean distance between 3-dimensional points in a periodic cell, using
+/// the minimum image convention.
+///
+/// The minimum image is found by wrapping the fractional coordinates inside
+/// the cell, and then checking the images in the 26 neighboring cells. This
+/// is exact as long as the cell is not too skewed, which is checked when
+/// creating the metric.
+#[derive(Debug, Clone, Copy)]
+pub struct Periodic {
+    /// Cell matrix, with the cell vectors as rows
+    cell: [[f64; 3]; 3],
+    /// Inverse of the cell matrix
+    inverse: [[f64; 3]; 3],
+    /// Are the cell vectors orthogonal? The wrapped vector is then always
+    /// the minimum image
+    orthogonal: bool,
+}
+
+impl Periodic {
+    /// Create a new periodic metric, the rows of the `cell` matrix are the
+    /// three cell vectors. This returns an error if the cell matrix can not be
+    /// inverted, or if the cell is too skewed for the minimum image to be in
+    /// one of the neighboring cells.
+    pub fn new(cell: [[f64; 3]; 3]) -> Result<Periodic, VoronoiError> {
+        let inverse = invert_3x3(&cell).ok_or(VoronoiError::SingularCell)?;
+
+        // The wrapped vector is inside the cell, so it is shorter than half
+        // of the longest diagonal of the cell. The images outside of the
+        // neighboring cells are at least 1.5 times the smallest distance
+        // between opposite faces of the cell away, and can not be shorter
+        // than the wrapped vector if this is larger than the half diagonal.
+        let [a, b, c] = cell;
+        let mut half_diagonal2 = 0.0_f64;
+        for &(sb, sc) in &[(1.0, 1.0), (1.0, -1.0), (-1.0, 1.0), (-1.0, -1.0)] {
+            let diagonal = [a[0] + sb * b[0] + sc * c[0], a[1] + sb * b[1] + sc * c[1], a[2] + sb * b[2] + sc * c[2]];
+            half_diagonal2 = half_diagonal2.max(0.25 * dot(&diagonal, &diagonal));
+        }
+
+        let volume = dot(&a, &cross(&b, &c)).abs();
+        let mut min_height2 = f64::INFINITY;
+        for (u, v) in [(b, c), (c, a), (a, b)] {
+            let area = cross(&u, &v);
+            min_height2 = min_height2.min(volume * volume / dot(&area, &area));
+        }
+
+        if 2.25 * min_height2 < half_diagonal2 {
+            return Err(VoronoiError::SkewedCell);
+        }
+
+        Ok(Periodic {
+            cell: cell,
+            inverse: inverse,
+            orthogonal: dot(&a, &b) == 0.0 && dot(&b, &c) == 0.0 && dot(&c, &a) == 0.0,
+        })
+    }
+}
+
+impl Metric<f64> for Periodic {
+    fn distance2(&self, a: ArrayView1<'_, f64>, b: ArrayView1<'_, f64>) -> f64 {
+        let delta = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+
+        // go to fractional coordinates, and wrap the vector inside the cell
+        let mut fractional = vector_matrix(&delta, &self.inverse);
+        for f in &mut fractional {
+            *f -= f.round();
+        }
+
+        let wrapped = vector_matrix(&fractional, &self.cell);
+        let mut distance2 = dot(&wrapped, &wrapped);
+        if self.orthogonal {
+            return distance2;
+        }
+
+        // for skewed cells, the minimum image can be in a neighboring cell
+        for i in -1..=1 {
+            for j in -1..=1 {
+                for k in -1..=1 {
+                    let shifted = [fractional[0] + i as f64, fractional[1] + j as f64, fractional[2] + k as f64];
+                    let delta = vector_matrix(&shifted, &self.cell);
+                    distance2 = distance2.min(dot(&delta, &delta));
+                }
+            }
+        }
+
+        return distance2;
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(3)
+    }
+}
+
+/// Compute the dot product of two 3-dimensional vectors
+fn dot(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    return a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+}
+
+/// Compute the cross product of two 3-dimensional vectors
+fn cross(a: &[f64; 3], b: &[f64; 3]) -> [f64; 3] {
+    return [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ];
+}
+
+/// Compute the product of the row `vector` with `matrix`
+fn vector_matrix(vector: &[f64; 3], matrix: &[[f64; 3]; 3]) -> [f64; 3] {
+    let mut result = [0.0; 3];
+    for (i, r) in result.iter_mut().enumerate() {
+        *r = vector[0] * matrix[0][i] + vector[1] * matrix[1][i] + vector[2] * matrix[2][i];
+    }
+    return result;
+}
+
+/// Invert a 3x3 matrix, returning `None` if the matrix is singular
+fn invert_3x3(m: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let determinant = m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2])
+                    - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                    + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+
+    if determinant == 0.0 || !determinant.is_finite() {
+        return None;
+    }
+
+    let inv_det = 1.0 / determinant;
+    let mut inverse = [[0.0; 3]; 3];
+    inverse[0][0] = (m[1][1] * m[2][2] - m[2][1] * m[1][2]) * inv_det;
+    inverse[0][1] = (m[0][2] * m[2][1] - m[0][1] * m[2][2]) * inv_det;
+    inverse[0][2] = (m[0][1] * m[1][2] - m[0][2] * m[1][1]) * inv_det;
+    inverse[1][0] = (m[1][2] * m[2][0] - m[1][0] * m[2][2]) * inv_det;
+    inverse[1][1] = (m[0][0] * m[2][2] - m[0][2] * m[2][0]) * inv_det;
+    inverse[1][2] = (m[1][0] * m[0][2] - m[0][0] * m[1][2]) * inv_det;
+    inverse[2][0] = (m[1][0] * m[2][1] - m[2][0] * m[1][1]) * inv_det;
+    inverse[2][1] = (m[2][0] * m[0][1] - m[0][0] * m[2][1]) * inv_det;
+    inverse[2][2] = (m[0][0] * m[1][1] - m[1][0] * m[0][1]) * inv_det;
+
+    return Some(inverse);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::VoronoiDecomposer;
+
+    #[test]
+    fn stable_euclidean() {
+        // one large difference, and a lot of small ones which are lost when
+        // accumulating in f32
+        let mut a = ndarray::Array1::<f32>::zeros(1001);
+        let b = ndarray::Array1::<f32>::zeros(1001);
+        a[0] = 1e4;
+        a.slice_mut(ndarray::s![1..]).fill(0.1);
+
+        let exact = 1e8 + 1000.0 * 0.01;
+        let naive = Metric::<f32>::distance2(&Euclidean, a.view(), b.view()) as f64;
+        let stable = Metric::<f32>::distance2(&StableEuclidean, a.view(), b.view()) as f64;
+
+        assert!((naive - exact).abs() >= 8.0);
+        assert!((stable - exact).abs() <= 4.0);
+    }
+
+    #[test]
+    fn mahalanobis() {
+        let inverse_covariance = ndarray::arr2(&[[2.0, 0.5, 0.0], [0.5, 1.0, 0.0], [0.0, 0.0, 4.0]]);
+        let metric = Mahalanobis::new(inverse_covariance.view()).unwrap();
+
+        let a = ndarray::arr1(&[1.0, 2.0, 3.0]);
+        let b = ndarray::arr1(&[0.5, -1.0, 2.0]);
+        let delta = &a - &b;
+        let expected = delta.dot(&inverse_covariance.dot(&delta));
+        assert!((metric.distance2(a.view(), b.view()) - expected).abs() < 1e-12);
+
+        let points = ndarray::Array2::<f64>::zeros((4, 2));
+        let error = VoronoiDecomposer::try_new_with_metric(points.view().into(), 0, metric).unwrap_err();
+        assert_eq!(error, VoronoiError::InvalidDimension { expected: 3, got: 2 });
+
+        let error = Mahalanobis::new(ndarray::Array2::zeros((2, 3)).view()).unwrap_err();
+        assert_eq!(error, VoronoiError::NotSquare { rows: 2, columns: 3 });
+
+        let error = Mahalanobis::new(ndarray::arr2(&[[1.0, 0.5], [0.2, 1.0]]).view()).unwrap_err();
+        assert_eq!(error, VoronoiError::NotSymmetric { i: 0, j: 1 });
+
+        let error = Mahalanobis::new(ndarray::arr2(&[[1.0, 2.0], [2.0, 1.0]]).view()).unwrap_err();
+        assert_eq!(error, VoronoiError::NotPositiveDefinite);
+    }
+
+    #[test]
+    fn haversine() {
+        let paris = ndarray::arr1(&[48.8566, 2.3522]);
+        let london = ndarray::arr1(&[51.5074, -0.1278]);
+        let new_york = ndarray::arr1(&[40.7128, -74.0060]);
+        let los_angeles = ndarray::arr1(&[34.0522, -118.2437]);
+
+        let metric = Haversine::default();
+        let distance = Metric::<f64>::distance2(&metric, paris.view(), london.view()).sqrt();
+        assert!((distance - 343.557).abs() < 1e-3);
+
+        let distance = Metric::<f64>::distance2(&metric, new_york.view(), los_angeles.view()).sqrt();
+        assert!((distance - 3935.752).abs() < 1e-3);
+
+        // antipodal points are half the circumference apart
+        let a = ndarray::arr1(&[0.0, 0.0]);
+        let b = ndarray::arr1(&[0.0, 180.0]);
+        let distance = Metric::<f64>::distance2(&Haversine::new(1.0), a.view(), b.view()).sqrt();
+        assert!((distance - std::f64::consts::PI).abs() < 1e-12);
+
+        let points = ndarray::Array2::<f64>::zeros((4, 3));
+        let error = VoronoiDecomposer::try_new_with_metric(points.view().into(), 0, metric).unwrap_err();
+        assert_eq!(error, VoronoiError::InvalidDimension { expected: 2, got: 3 });
+    }
+
+    #[test]
+    fn periodic() {
+        // the wrapped vector is (-0.4, -0.3, 0), with a squared norm of 0.25,
+        // but the minimum image is (0.1, -0.4, 0) in the neighboring cell
+        let metric = Periodic::new([[1.0, 0.0, 0.0], [0.5, 0.7, 0.0], [0.0, 0.0, 1.0]]).unwrap();
+        let a = ndarray::arr1(&[0.0, 0.0, 0.0]);
+        let b = ndarray::arr1(&[0.9, -1.0, 1.0]);
+        assert!((metric.distance2(a.view(), b.view()) - 0.17).abs() < 1e-12);
+
+        let metric = Periodic::new([[2.0, 0.0, 0.0], [0.0, 3.0, 0.0], [0.0, 0.0, 4.0]]).unwrap();
+        let b = ndarray::arr1(&[1.5, 2.0, -3.5]);
+        assert!((metric.distance2(a.view(), b.view()) - 1.5).abs() < 1e-12);
+
+        let error = Periodic::new([[1.0, 0.0, 0.0], [0.9, 0.5, 0.0], [0.0, 0.0, 1.0]]).unwrap_err();
+        assert_eq!(error, VoronoiError::SkewedCell);
+    }
+}
@@ -3,7 +3,12 @@ use pyo3::prelude::*;
 use std::cell::RefCell;
 
 use ndarray::Array2;
-use numpy::{PyArray1, PyArray2};
+use numpy::{PyArray1, PyArray2, PyReadonlyArray2};
+use pyo3::exceptions::PyValueError;
+
+fn to_py_error(error: crate::VoronoiError) -> PyErr {
+    return PyValueError::new_err(error.to_string());
+}
 
 #[pyclass]
 pub struct VoronoiDecomposer {
@@ -13,20 +18,27 @@ pub struct VoronoiDecomposer {
 #[pymethods]
 impl VoronoiDecomposer {
     #[new]
-    fn new(points: &PyArray2<f64>, initial: usize) -> Self {
-        let points = points.readonly();
-
+    fn new(points: PyReadonlyArray2<f64>, initial: usize) -> PyResult<Self> {
+        // the decomposer outlives the numpy array, so it needs its own copy
         let points: Array2<f64> = points.as_array().to_owned();
+        let decomposer = crate::VoronoiDecomposer::try_new(points.into(), initial).map_err(to_py_error)?;
 
-        VoronoiDecomposer {
-            decomposer: RefCell::new(crate::VoronoiDecomposer::new(points.into(), initial)),
-        }
+        Ok(VoronoiDecomposer {
+            decomposer: RefCell::new(decomposer),
+        })
     }
 
-    fn add_point(&self, new_point: usize) -> f64 {
+    fn add_point(&self, new_point: usize) -> PyResult<f64> {
         let mut decomposer = self.decomposer.borrow_mut();
-        decomposer.add_point(new_point);
-        return *decomposer.cells().last().unwrap().radius2;
+        decomposer.try_add_point(new_point).map_err(to_py_error)?;
+        return Ok(*decomposer.cells().radius2.last().unwrap());
+    }
+
+    /// Select `n` new points, and return their indexes
+    fn select<'a>(&self, py: Python<'a>, n: usize) -> &'a PyArray1<usize> {
+        let mut decomposer = self.decomposer.borrow_mut();
+        let selected = decomposer.select(n);
+        return PyArray1::from_vec(py, selected);
     }
 
     fn next_point(&self) -> (usize, f64) {
@@ -34,12 +46,23 @@ impl VoronoiDecomposer {
         return decomposer.next_point();
     }
 
+    /// Squared radius of each Voronoï cell, in selection order
+    #[getter]
     fn radius2<'a>(&self, py: Python<'a>) -> &'a PyArray1<f64> {
         let decomposer = self.decomposer.borrow();
         let r2 = decomposer.cells().radius2;
 
+        // the cells are modified by the next selection, so we can not share
+        // this memory with numpy
         return PyArray1::from_slice(py, r2);
     }
+
+    /// Indexes of the selected points, in selection order
+    #[getter]
+    fn selected<'a>(&self, py: Python<'a>) -> &'a PyArray1<usize> {
+        let decomposer = self.decomposer.borrow();
+        return PyArray1::from_slice(py, decomposer.selected());
+    }
 }
 
 
@@ -61,5 +84,6 @@ fn farthest_sampler(_: Python, m: &PyModule) -> PyResult<()> {
     }
 
     m.add_class::<VoronoiDecomposer>()?;
+    m.add("VoronoiFPS", m.getattr("VoronoiDecomposer")?)?;
     Ok(())
 }
@@ -0,0 +1,198 @@
+use ndarray::ArrayView2;
+
+use crate::{Float, VoronoiDecomposer, VoronoiError};
+
+/// Incremental Farthest Points Sampling over a dataset arriving in multiple
+/// shards (for example one `.npy` file per shard), keeping a single global
+/// selection.
+///
+/// Shards are added with `add_shard`, and points can be selected after each
+/// shard. New shards are added to the candidates with
+/// [`VoronoiDecomposer::extend_dataset`], so points selected from previous
+/// shards stay selected while the following selections consider all the
+/// shards added so far.
+///
+/// All indexes are global, i.e. positions in the concatenation of all the
+/// shards in the order they were added. Use `locate` to get the shard and the
+/// position inside the shard of a global index.
+///
+/// The decomposer owns a copy of all the shards, so the shards do not need to
+/// be kept alive by the caller, but the whole dataset still has to fit in
+/// memory.
+///
+/// ```
+/// # use farthest_sampler::ShardedSelector;
+/// let shard = |offset: f64| ndarray::Array2::from_shape_fn((50, 3), |(i, j)| offset + ((i * (j + 3)) as f64).sin());
+///
+/// let mut selector = ShardedSelector::new(0);
+/// selector.add_shard(shard(0.0).view()).unwrap();
+/// selector.select_fraction(0.1).unwrap();
+///
+/// selector.add_shard(shard(5.0).view()).unwrap();
+/// selector.select_fraction(0.1).unwrap();
+///
+/// assert_eq!(selector.selected().len(), 10);
+/// assert_eq!(selector.locate(60), Some((1, 10)));
+/// ```
+pub struct ShardedSelector<T: Float = f64> {
+    /// Index of the initial point in the first shard
+    initial: usize,
+    /// Decomposer containing all the shards, created with the first shard
+    voronoi: Option<VoronoiDecomposer<'static, T>>,
+    /// Global index of the first point of each shard
+    offsets: Vec<usize>,
+}
+
+impl<T: Float> ShardedSelector<T> {
+    /// Create a new selector, using the Euclidean distance. The point at
+    /// index `initial` in the first shard is the first selected point.
+    pub fn new(initial: usize) -> ShardedSelector<T> {
+        ShardedSelector {
+            initial: initial,
+            voronoi: None,
+            offsets: Vec::new(),
+        }
+    }
+
+    /// Add a new `shard` of points after the existing ones, and return the
+    /// index of the shard. The points are copied, and are candidates for the
+    /// following selections.
+    ///
+    /// This returns an error if the initial point is not in the first shard,
+    /// if the shard does not have the same number of features as the
+    /// previous ones, or if some of the new distances are NaN or infinite.
+    /// The shard is not added if this returns an error.
+    pub fn add_shard(&mut self, shard: ArrayView2<'_, T>) -> Result<usize, VoronoiError> {
+        let offset = self.n_points();
+        match self.voronoi {
+            Some(ref mut voronoi) => {
+                voronoi.extend_dataset(shard)?;
+                self.offsets.push(offset);
+            }
+            None => {
+                let voronoi = VoronoiDecomposer::try_new(shard.to_owned().into(), self.initial)?;
+                self.voronoi = Some(voronoi);
+                self.offsets.push(offset);
+            }
+        }
+
+        return Ok(self.offsets.len() - 1);
+    }
+
+    /// Select up to `n` new points from all the shards added so far, and
+    /// return their global indexes. See [`VoronoiDecomposer::select`].
+    ///
+    /// This returns an error if no shard was added yet.
+    pub fn select(&mut self, n: usize) -> Result<Vec<usize>, VoronoiError> {
+        let voronoi = self.voronoi.as_mut().ok_or(VoronoiError::EmptyDataset)?;
+        return Ok(voronoi.select(n));
+    }
+
+    /// Select new points until the total number of selected points (including
+    /// the initial point and the points selected from previous shards) is at
+    /// least `fraction` of the number of points added so far, rounding up.
+    /// This returns the global indexes of the points selected by this call.
+    ///
+    /// # Panics
+    ///
+    /// If `fraction` is not between 0 and 1.
+    pub fn select_fraction(&mut self, fraction: f64) -> Result<Vec<usize>, VoronoiError> {
+        assert!((0.0..=1.0).contains(&fraction), "the fraction must be between 0 and 1");
+        let target = (fraction * self.n_points() as f64).ceil() as usize;
+        let n_selected = self.selected().len();
+        return self.select(target.saturating_sub(n_selected));
+    }
+
+    /// Get the global indexes of all the selected points, in selection order
+    pub fn selected(&self) -> &[usize] {
+        match self.voronoi {
+            Some(ref voronoi) => voronoi.selected(),
+            None => &[],
+        }
+    }
+
+    /// Get the total number of points in all the shards
+    pub fn n_points(&self) -> usize {
+        match self.voronoi {
+            Some(ref voronoi) => voronoi.assignments().len(),
+            None => 0,
+        }
+    }
+
+    /// Get the number of shards added so far
+    pub fn n_shards(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Get the shard containing the point at the global index `index`, and
+    /// the position of the point in this shard. This returns `None` if
+    /// `index` is out of bounds.
+    pub fn locate(&self, index: usize) -> Option<(usize, usize)> {
+        if index >= self.n_points() {
+            return None;
+        }
+
+        // the last shard starting at or before `index`, skipping empty shards
+        let shard = self.offsets.partition_point(|&offset| offset <= index) - 1;
+        return Some((shard, index - self.offsets[shard]));
+    }
+
+    /// Get the underlying decomposer, or `None` if no shard was added yet
+    pub fn decomposer(&self) -> Option<&VoronoiDecomposer<'static, T>> {
+        self.voronoi.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ndarray::s;
+
+    use crate::voronoi::test::boston;
+
+    #[test]
+    fn shards() {
+        let data = boston();
+
+        let mut selector = ShardedSelector::new(3);
+        assert_eq!(selector.select(10), Err(VoronoiError::EmptyDataset));
+
+        let mut selected = vec![3];
+        assert_eq!(selector.add_shard(data.slice(s![..200, ..])).unwrap(), 0);
+        selected.extend(selector.select(20).unwrap());
+        assert!(selected.iter().all(|&i| i < 200));
+
+        // empty shards are allowed
+        assert_eq!(selector.add_shard(data.slice(s![200..200, ..])).unwrap(), 1);
+        assert_eq!(selector.add_shard(data.slice(s![200.., ..])).unwrap(), 2);
+        selected.extend(selector.select_fraction(0.1).unwrap());
+        assert_eq!(selector.selected(), selected);
+        assert_eq!(selected.len(), 51);
+
+        // same as continuing a selection on the whole dataset
+        let mut expected = VoronoiDecomposer::new_from_selection(data.view().into(), &selected[..21]).unwrap();
+        assert_eq!(expected.select(30), selected[21..]);
+
+        assert_eq!(selector.n_points(), 506);
+        assert_eq!(selector.n_shards(), 3);
+        assert_eq!(selector.locate(0), Some((0, 0)));
+        assert_eq!(selector.locate(199), Some((0, 199)));
+        assert_eq!(selector.locate(200), Some((2, 0)));
+        assert_eq!(selector.locate(505), Some((2, 305)));
+        assert_eq!(selector.locate(506), None);
+
+        let error = ShardedSelector::<f64>::new(0).add_shard(data.slice(s![..0, ..])).err();
+        assert_eq!(error, Some(VoronoiError::EmptyDataset));
+
+        let error = selector.add_shard(data.slice(s![.., ..3])).err();
+        assert_eq!(error, Some(VoronoiError::InvalidDimension { expected: 13, got: 3 }));
+
+        let mut invalid = data.slice(s![..2, ..]).to_owned();
+        invalid[[0, 0]] = f64::NAN;
+        let error = selector.add_shard(invalid.view()).err();
+        assert_eq!(error, Some(VoronoiError::NaNDistance { index: 506 }));
+        assert_eq!(selector.n_points(), 506);
+        assert_eq!(selector.n_shards(), 3);
+    }
+}
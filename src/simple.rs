@@ -1,6 +1,7 @@
-use ndarray::{Array1, ArrayView1, ArrayView2, ArrayViewMut1, Axis, par_azip, s};
+use ndarray::{Array1, ArrayView1, ArrayView2, ArrayViewMut1, Axis, s};
 
-use super::find_max;
+use super::{find_max, Float};
+use crate::distances::{Distances, EuclideanDistances};
 
 fn compute_haussdorf(
     points: ArrayView2<'_, f64>,
@@ -9,14 +10,25 @@ fn compute_haussdorf(
     output: ArrayViewMut1<f64>
 ) {
     let point = points.slice(s![current, ..]);
-    par_azip!((o in output, norm in norms, other in points.axis_iter(Axis(0))) {
+
+    #[cfg(feature = "rayon")]
+    ndarray::par_azip!((o in output, norm in norms, other in points.axis_iter(Axis(0))) {
+        *o = norm + norms[current] - 2.0 * point.dot(&other);
+    });
+
+    #[cfg(not(feature = "rayon"))]
+    ndarray::azip!((o in output, norm in norms, other in points.axis_iter(Axis(0))) {
         *o = norm + norms[current] - 2.0 * point.dot(&other);
-    })
+    });
 }
 
-/// Select `n_select` points from `points` using Farthest Points Sampling, and
-/// return the indexes of selected points. The first point (already selected) is
-/// the point at the `initial` index.
+/// Select up to `n_select` points from `points` using Farthest Points Sampling,
+/// and return the indexes of selected points. The first point (already
+/// selected) is the point at the `initial` index.
+///
+/// The selection stops early once all the remaining points are at a distance
+/// of zero from the selected ones (for example with duplicated points), like
+/// `voronoi::select_fps`.
 #[cfg_attr(feature = "time-graph", time_graph::instrument)]
 pub fn select_fps(points: ArrayView2<'_, f64>, n_select: usize, initial: usize) -> Vec<usize> {
     let n_points = points.nrows();
@@ -25,6 +37,14 @@ pub fn select_fps(points: ArrayView2<'_, f64>, n_select: usize, initial: usize)
         panic!("can not select more points than what we have")
     }
 
+    if n_points == 0 {
+        panic!("can not select points from an empty dataset")
+    }
+
+    if initial >= n_points {
+        panic!("index {} out of range for {} points", initial, n_points)
+    }
+
     let norms = points.axis_iter(Axis(0))
         .map(|row| row.dot(&row))
         .collect::<Array1<f64>>();
@@ -37,7 +57,10 @@ pub fn select_fps(points: ArrayView2<'_, f64>, n_select: usize, initial: usize)
     let mut new_distances = Array1::from_elem([n_points], 0.0);
 
     for _ in 1..n_select {
-        let (new, _) = find_max(haussdorf.iter());
+        let (new, max) = find_max(haussdorf.iter());
+        if max <= 0.0 {
+            break;
+        }
         fps_indexes.push(new);
 
         compute_haussdorf(points, norms.view(), new, new_distances.view_mut());
@@ -52,6 +75,54 @@ pub fn select_fps(points: ArrayView2<'_, f64>, n_select: usize, initial: usize)
     return fps_indexes;
 }
 
+/// Select `n_select` points from `points` using a plain O(N n_select)
+/// implementation of Farthest Points Sampling, starting from the point at the
+/// `initial` index, and return the indexes of selected points.
+///
+/// This computes the same distances as `VoronoiDecomposer`, and gives exactly
+/// the same selection, including stopping early once all the remaining points
+/// are at a distance of zero from the selected ones. It can be faster for
+/// small datasets, and serves as a reference to check the Voronoï
+/// decomposition.
+pub fn fps_bruteforce<T: Float>(points: ArrayView2<'_, T>, initial: usize, n_select: usize) -> Vec<usize> {
+    let n_points = points.nrows();
+    if n_select > n_points {
+        panic!("can not select more points than what we have")
+    }
+
+    if n_points == 0 {
+        panic!("can not select points from an empty dataset")
+    }
+
+    if initial >= n_points {
+        panic!("index {} out of range for {} points", initial, n_points)
+    }
+
+    let distances = EuclideanDistances::new(points.into());
+    let mut haussdorf = (0..n_points).map(|point| distances.distance2(initial, point)).collect::<Vec<_>>();
+
+    let mut selected = Vec::with_capacity(n_select);
+    if n_select > 0 {
+        selected.push(initial);
+    }
+
+    while selected.len() < n_select {
+        let (new, max) = find_max(haussdorf.iter());
+        if max <= T::zero() {
+            break;
+        }
+        selected.push(new);
+
+        for (point, d) in haussdorf.iter_mut().enumerate() {
+            let new_d = distances.distance2(new, point);
+            if new_d < *d {
+                *d = new_d;
+            }
+        }
+    }
+
+    return selected;
+}
 
 #[cfg(test)]
 mod test {
@@ -59,8 +130,21 @@ mod test {
 
     use ndarray::Array2;
 
-    use ndarray_npy::read_npy;
-    use std::path::PathBuf;
+    use crate::voronoi::test::boston;
+
+    #[test]
+    #[should_panic = "index 5 out of range for 3 points"]
+    fn initial_out_of_range() {
+        let data = Array2::<f64>::zeros((3, 2));
+        select_fps(data.view(), 2, 5);
+    }
+
+    #[test]
+    #[should_panic = "can not select points from an empty dataset"]
+    fn empty_dataset() {
+        let data = Array2::<f64>::zeros((0, 2));
+        select_fps(data.view(), 0, 0);
+    }
 
     #[test]
     fn check_simple() {
@@ -71,18 +155,50 @@ mod test {
             1.0, 0.0,
         ]).unwrap();
 
-        let expected = vec![0, 3, 2, 1];
+        let expected = [0, 3, 2, 1];
         for n_select in 1..expected.len() {
             let selected = select_fps(data.view(), n_select, expected[0]);
             assert_eq!(selected, expected[..n_select]);
         }
     }
 
+    #[test]
+    fn duplicates() {
+        let data = Array2::from_shape_vec((4, 2), vec![
+            0.0, 0.0,
+            0.0, 0.0,
+            1.0, 1.0,
+            1.0, 1.0,
+        ]).unwrap();
+
+        assert_eq!(select_fps(data.view(), 4, 0), [0, 2]);
+        assert_eq!(select_fps(data.view(), 4, 0), crate::voronoi::select_fps(data.view(), 4, 0));
+    }
+
+    #[test]
+    fn bruteforce() {
+        let data = boston();
+
+        let data = data.t().as_standard_layout().to_owned();
+        let expected = vec![9, 3, 11, 6, 1, 10, 8, 0, 12, 2, 5, 7, 4];
+        for n_select in 0..13 {
+            let selected = fps_bruteforce(data.view(), expected[0], n_select);
+            assert_eq!(selected, expected[..n_select]);
+        }
+
+        // already selected points are never selected again
+        let data = Array2::from_shape_vec((4, 2), vec![
+            0.0, 0.0,
+            0.0, 0.0,
+            1.0, 1.0,
+            1.0, 1.0,
+        ]).unwrap();
+        assert_eq!(fps_bruteforce(data.view(), 0, 4), [0, 2]);
+    }
+
     #[test]
     fn check_boston() {
-        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        path.push("boston.npy");
-        let data: Array2<f64> = read_npy(path).unwrap();
+        let data = boston();
 
         let expected = vec![9, 3, 11, 6, 1, 10, 8, 0, 12, 2, 5, 7, 4];
         for n_select in 1..13 {
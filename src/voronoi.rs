@@ -1,51 +1,169 @@
-use std::cell::Cell;
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+use std::ops::ControlFlow;
 
+#[cfg(feature = "rayon")]
 use rayon::prelude::*;
-use thread_local::ThreadLocal;
 
-use soa_derive::{StructOfArray, soa_zip};
-use ndarray::{Array1, ArrayView2, Axis, CowArray, Ix2, s};
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2, Axis, CowArray, Ix2};
 
+use crate::{Float, Metric, VoronoiError};
+use crate::metric::Periodic;
+use crate::distances::{Distances, EuclideanDistances, GramDistances, MetricDistances, MultiViewDistances, SparseDistances};
 use super::find_max;
 
-/// Single Voronoï cell
-#[derive(StructOfArray, Debug)]
-#[soa_derive = "Debug"]
-pub struct VoronoiCell {
-    /// Position of the center of the cell
-    center: Array1<f64>,
+/// Voronoï cells, stored as a struct of arrays
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct VoronoiCells<T> {
     /// Index of the cell center among all points
-    center_idx: usize,
-    /// Index of the point farthest from the center in this cell
-    farthest: usize,
-    /// Distance (squared) to the farthest point from the center in this cell
-    radius2: f64,
-    /// Indexes of the points in this cell
-    points: Vec<usize>,
+    center_idx: Vec<usize>,
+    /// Index of the point farthest from the center in each cell
+    farthest: Vec<usize>,
+    /// Distance (squared) to the farthest point from the center in each cell
+    radius2: Vec<T>,
+    /// Indexes of the points in each cell
+    points: Vec<Vec<usize>>,
 }
 
-/// Point farthest away from a the center of a cell
-#[derive(Clone, Debug, PartialEq, Default)]
-struct FarthestPoint {
-    pub distance2: Cell<f64>,
-    pub index: Cell<usize>,
+impl<T> VoronoiCells<T> {
+    fn new() -> VoronoiCells<T> {
+        VoronoiCells {
+            center_idx: Vec::new(),
+            farthest: Vec::new(),
+            radius2: Vec::new(),
+            points: Vec::new(),
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.center_idx.reserve(additional);
+        self.farthest.reserve(additional);
+        self.radius2.reserve(additional);
+        self.points.reserve(additional);
+    }
+
+    fn push(&mut self, center_idx: usize, farthest: usize, radius2: T, points: Vec<usize>) {
+        self.center_idx.push(center_idx);
+        self.farthest.push(farthest);
+        self.radius2.push(radius2);
+        self.points.push(points);
+    }
+
+    fn pop(&mut self) -> Option<(usize, Vec<usize>)> {
+        self.farthest.pop()?;
+        self.radius2.pop()?;
+        let center_idx = self.center_idx.pop()?;
+        let points = self.points.pop()?;
+        return Some((center_idx, points));
+    }
+
+    fn as_slice(&self) -> VoronoiCellSlice<'_, T> {
+        VoronoiCellSlice {
+            center_idx: &self.center_idx,
+            farthest: &self.farthest,
+            radius2: &self.radius2,
+            points: &self.points,
+        }
+    }
+}
+
+/// View inside the Voronoï cells of a `VoronoiDecomposer`, stored as a struct
+/// of arrays. The same index in all arrays refers to the same cell.
+#[derive(Debug, Clone, Copy)]
+pub struct VoronoiCellSlice<'a, T> {
+    /// Index of the cell center among all points
+    pub center_idx: &'a [usize],
+    /// Index of the point farthest from the center in each cell. This is
+    /// always a point of the cell `points[i]`, at a squared distance
+    /// `radius2[i]` from `center_idx[i]`. If multiple points are at the same
+    /// distance, this is the one with the smallest index.
+    pub farthest: &'a [usize],
+    /// Distance (squared) to the farthest point from the center in each cell
+    pub radius2: &'a [T],
+    /// Indexes of the points in each cell
+    pub points: &'a [Vec<usize>],
+}
+
+impl<'a, T> VoronoiCellSlice<'a, T> {
+    /// Get the number of cells
+    pub fn len(&self) -> usize {
+        self.center_idx.len()
+    }
+
+    /// Check if there are no cells
+    pub fn is_empty(&self) -> bool {
+        self.center_idx.is_empty()
+    }
+}
+
+impl<'a, T: Float> VoronoiCellSlice<'a, T> {
+    /// Get the distance (not squared) to the farthest point from the center
+    /// in each cell
+    pub fn radius(&self) -> Vec<T> {
+        self.radius2.iter().map(|r2| r2.sqrt()).collect()
+    }
+}
+
+/// Owned copy of the state of the cells at some point during the selection,
+/// created by [`VoronoiDecomposer::snapshot_cells`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellsSnapshot<T> {
+    /// Index of the center of each cell, i.e. the selected points
+    pub center_idx: Vec<usize>,
+    /// Index of the farthest point from the center in each cell
+    pub farthest: Vec<usize>,
+    /// Squared radius of each cell
+    pub radius2: Vec<T>,
+}
+
+/// Center, farthest point and radius of a single cell, returned by
+/// [`VoronoiDecomposer::cell_infos`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellInfo<T> {
+    /// Index of the center of the cell, i.e. the selected point
+    pub center: usize,
+    /// Index of the farthest point from the center in the cell
+    pub farthest: usize,
+    /// Squared radius of the cell
+    pub radius2: T,
+}
+
+/// Summary of how well the selected points cover the full dataset
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage<T> {
+    /// Largest squared radius of the cells, i.e. the squared (directed)
+    /// Hausdorff distance from the dataset to the selected points
+    pub max_radius2: T,
+    /// Mean squared distance from all points to the closest selected point
+    pub mean_distance2: T,
+    /// 50th, 90th and 99th percentile of the squared distance from all
+    /// points to the closest selected point, see
+    /// [`VoronoiDecomposer::distance2_quantiles`]
+    pub quantiles_distance2: [T; 3],
+    /// Histogram of the cell sizes: for each size, the number of cells
+    /// containing this number of points
+    pub cell_sizes: BTreeMap<usize, usize>,
 }
 
 /// allocation cache for `VoronoiDecomposer` when adding a new point
 #[derive(Debug)]
-struct WorkArrays {
+struct WorkArrays<T> {
     /// Distance between the new point and the center of all cells
-    distance_to_new_point: Vec<f64>,
+    distance_to_new_point: Vec<T>,
     /// List of active cells that might need to change
     active_cells: HashSet<usize>,
+    /// List of cells that changed since the last update of their radius
+    modified_cells: HashSet<usize>,
 }
 
-impl WorkArrays {
-    fn new() -> WorkArrays {
+impl<T> WorkArrays<T> {
+    fn new() -> WorkArrays<T> {
         WorkArrays {
             distance_to_new_point: Vec::new(),
             active_cells: HashSet::new(),
+            modified_cells: HashSet::new(),
         }
     }
 
@@ -57,251 +175,3596 @@ impl WorkArrays {
     fn reserve(&mut self, additional: usize) {
         self.distance_to_new_point.reserve(additional);
         self.active_cells.reserve(additional);
+        self.modified_cells.reserve(additional);
+    }
+}
+
+/// Points which can never be selected, together with the farthest point
+/// which can be selected in each cell
+#[derive(Debug, Clone)]
+struct Excluded<T> {
+    /// Whether each point is excluded
+    mask: Vec<bool>,
+    /// Farthest point which is not excluded in each cell, or the center of
+    /// the cell if all the other points are excluded
+    farthest: Vec<usize>,
+    /// Distance (squared) between the center and `farthest` in each cell
+    radius2: Vec<T>,
+}
+
+impl<T: Float> Excluded<T> {
+    /// Find the farthest point which is not excluded among the `points` of a
+    /// cell, with the same tie-breaking as the farthest point of the cell
+    fn candidate(&self, center: usize, points: &[usize], haussdorf: &[T]) -> (usize, T) {
+        let mut farthest = center;
+        let mut radius2 = T::zero();
+        for &point in points {
+            if self.mask[point] {
+                continue;
+            }
+
+            if haussdorf[point] > radius2 || (haussdorf[point] == radius2 && point < farthest) {
+                radius2 = haussdorf[point];
+                farthest = point;
+            }
+        }
+        return (farthest, radius2);
     }
 }
 
+/// Maximal distance used to stop the selection, together with the points
+/// found to be isolated by this distance
+#[derive(Debug, Clone)]
+struct Cutoff<T> {
+    /// Squared cutoff distance
+    cutoff2: T,
+    /// Points without any other point within the cutoff, in the order they
+    /// were found. These points are also part of the excluded points.
+    outliers: Vec<usize>,
+}
+
+/// Voronoï decomposition of a set of points, used to run Farthest Points
+/// Sampling. `T` is the floating point type used for the points and distances.
 #[derive(Debug)]
-pub struct VoronoiDecomposer<'a> {
-    /// Input points
-    points: CowArray<'a, f64, Ix2>,
+pub struct VoronoiDecomposer<'a, T = f64> {
+    /// Distances between the input points
+    distances: Box<dyn Distances<T> + 'a>,
     /// Current list of cells
-    cells: VoronoiCellVec,
-    /// Norm of the vector from origin for each points
-    norms: Vec<f64>,
+    cells: VoronoiCells<T>,
     /// Shortest distance for each point to already selected points
-    haussdorf: Vec<f64>,
+    haussdorf: Vec<T>,
+    /// Index of the cell containing each point
+    assignments: Vec<usize>,
+    /// Largest distance between the initial point and any other point
+    initial_radius2: T,
+    /// Smallest distance between two selected points
+    min_center_distance2: T,
+    /// Number of points for which the distance to the last selected point
+    /// was computed
+    last_recompute_count: usize,
+    /// Events recorded for each new cell, if tracing is enabled
+    trace: Option<Vec<AddPointEvent<T>>>,
+    /// Optional weight of each point, multiplying the radius of the cells
+    /// when looking for the next point
+    weights: Option<Vec<T>>,
+    /// Points which can not be selected by FPS, if any
+    excluded: Option<Excluded<T>>,
+    /// Cutoff distance for the selection, if any
+    cutoff: Option<Cutoff<T>>,
+    /// Smallest squared distance between a new selected point and the
+    /// existing ones
+    min_separation2: T,
+    /// Number of reference points, stored after the other points and
+    /// selected first (see `new_with_reference`)
+    n_reference: usize,
+    /// Time spent in the different phases of the selection
+    #[cfg(feature = "metrics")]
+    timings: std::sync::Mutex<Timings>,
+    /// Number of points processed together when computing distances to a
+    /// new point
+    block_size: usize,
     /// Cached allocations when adding new points
-    work: WorkArrays,
+    work: WorkArrays<T>,
+}
+
+/// Information about the addition of a single point to a `VoronoiDecomposer`,
+/// recorded when tracing is enabled with
+/// [`VoronoiDecomposer::enable_trace`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddPointEvent<T> {
+    /// Index of the added point
+    pub point: usize,
+    /// Squared distance between the added point and the closest previously
+    /// selected point. For points selected by FPS, this is the radius that
+    /// triggered the selection.
+    pub radius2: T,
+    /// Number of points moved to the cell of the new point
+    pub n_reassigned: usize,
+    /// Indexes of the cells which lost points to the new cell
+    pub source_cells: Vec<usize>,
+    /// Number of distances computed to add this point, to compare with the
+    /// number of points for brute force FPS
+    pub n_distances: usize,
+}
+
+/// Time spent in the different phases of the selection, recorded with the
+/// `metrics` feature and retrieved with [`VoronoiDecomposer::take_timings`]
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timings {
+    /// Time spent computing the distances to the initial point when creating
+    /// the decomposer. This does not include the preparation of the points,
+    /// such as computing their norms for the Euclidean distance.
+    pub construction: std::time::Duration,
+    /// Total time spent adding new points to the decomposition, with
+    /// `add_point`, `add_points` or the selection functions
+    pub add_points: std::time::Duration,
+    /// Number of calls to `add_points` included in `add_points`
+    pub n_add_points: usize,
+    /// Total time spent looking for the cell containing the next point to
+    /// select
+    pub candidate_scans: std::time::Duration,
+    /// Number of scans included in `candidate_scans`
+    pub n_candidate_scans: usize,
 }
 
-impl<'a> VoronoiDecomposer<'a> {
+/// Default number of points in a block when computing distances
+const DEFAULT_BLOCK_SIZE: usize = 64;
+
+impl<'a, T: Float> VoronoiDecomposer<'a, T> {
+    /// Create a new decomposer for the given `points`, using the Euclidean
+    /// distance. The point at index `initial` is the first selected point.
+    ///
+    /// `points` can be any view, including the transpose of a `(n_features,
+    /// n_samples)` array (`array.t().into()`). Rows are then accessed with a
+    /// stride, which is slower than contiguous rows for large arrays: use
+    /// `as_standard_layout()` to make a contiguous copy when memory allows.
+    ///
+    /// # Panics
+    ///
+    /// If `points` is empty, `initial` is out of bounds, or some distances
+    /// are NaN. See [`VoronoiDecomposer::try_new`] for a fallible version.
+    pub fn new(points: CowArray<'a, T, Ix2>, initial: usize) -> VoronoiDecomposer<'a, T> {
+        return VoronoiDecomposer::try_new(points, initial).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Create a new decomposer for the given `points` using the Euclidean
+    /// distance, starting the selection from the first point. This is the
+    /// same as `VoronoiDecomposer::new(points, 0)`.
+    ///
+    /// # Panics
+    ///
+    /// If `points` is empty or some distances are NaN. The `TryFrom`
+    /// implementation for `ArrayView2` is a fallible version.
+    pub fn from_points(points: CowArray<'a, T, Ix2>) -> VoronoiDecomposer<'a, T> {
+        return VoronoiDecomposer::new(points, 0);
+    }
+
+    /// Same as [`VoronoiDecomposer::new`], but returns an error instead of
+    /// panicking if `points` is empty, `initial` is out of bounds, or some
+    /// distances are NaN.
+    pub fn try_new(points: CowArray<'a, T, Ix2>, initial: usize) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        return VoronoiDecomposer::try_from_distances(Box::new(EuclideanDistances::new(points)), initial);
+    }
+
+    /// Same as [`VoronoiDecomposer::try_new`], but use a random projection
+    /// of the points to `target_dim` dimensions to skip some distance
+    /// computations, see [`VoronoiDecomposerBuilder::random_projection`].
+    ///
+    /// [`VoronoiDecomposerBuilder::random_projection`]: crate::VoronoiDecomposerBuilder::random_projection
+    #[cfg(feature = "random-projection")]
+    pub(crate) fn try_new_projected(points: CowArray<'a, T, Ix2>, initial: usize, target_dim: usize, seed: u64) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        let distances = crate::distances::ProjectedDistances::new(points, target_dim, seed);
+        return VoronoiDecomposer::try_from_distances(Box::new(distances), initial);
+    }
+
+    /// Same as [`VoronoiDecomposer::new`], but reserve space for
+    /// `expected_selections` selected points up front. This avoids repeated
+    /// re-allocations of the per-cell arrays when selecting many points.
+    pub fn with_capacity(points: CowArray<'a, T, Ix2>, initial: usize, expected_selections: usize) -> VoronoiDecomposer<'a, T> {
+        let mut voronoi = VoronoiDecomposer::new(points, initial);
+        voronoi.reserve(expected_selections);
+        return voronoi;
+    }
+
+    /// Create a new decomposer for weighted FPS on the given `points`, using
+    /// the Euclidean distance. The point at index `initial` is the first
+    /// selected point.
+    ///
+    /// The next selected point is the farthest point of the cell with the
+    /// highest `weights[farthest] * radius2`, biasing the selection towards
+    /// points with high weight. The Voronoï decomposition itself still uses
+    /// the distances between points. This returns an error if there is not
+    /// exactly one weight per point, or if some weights are negative.
+    pub fn new_weighted(points: CowArray<'a, T, Ix2>, weights: ArrayView1<'_, T>, initial: usize) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        if weights.len() != points.nrows() {
+            return Err(VoronoiError::InvalidWeights { expected: points.nrows(), got: weights.len() });
+        }
+
+        if let Some(index) = weights.iter().position(|&w| w < T::zero() || w.is_nan()) {
+            return Err(VoronoiError::NegativeWeight { index });
+        }
+
+        let mut voronoi = VoronoiDecomposer::try_new(points, initial)?;
+        voronoi.weights = Some(weights.to_vec());
+        return Ok(voronoi);
+    }
+
+    /// Create a new decomposer for the given `points`, using the Euclidean
+    /// distance, where the points at the indexes in `excluded` are never
+    /// selected by FPS. The point at index `initial` is the first selected
+    /// point, and can not be one of the excluded points.
+    ///
+    /// The excluded points are still part of the Voronoï cells, and are
+    /// included in the radius of the cells (e.g. in `cells()` or
+    /// `add_group`). `next_candidate`, `next_point` and all the `select`
+    /// functions skip them, returning the farthest point which is not
+    /// excluded together with its squared distance to the closest selected
+    /// point. Excluded points can still be added manually with `add_point`.
+    pub fn new_with_excluded(points: CowArray<'a, T, Ix2>, initial: usize, excluded: &[usize]) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        let n_points = points.nrows();
+        let mut mask = vec![false; n_points];
+        for &index in excluded {
+            if index >= n_points {
+                return Err(VoronoiError::IndexOutOfBounds { index, n_points });
+            }
+            mask[index] = true;
+        }
+
+        if initial < n_points && mask[initial] {
+            return Err(VoronoiError::ExcludedInitial { index: initial });
+        }
+
+        let mut voronoi = VoronoiDecomposer::try_new(points, initial)?;
+        let mut excluded = Excluded {
+            mask: mask,
+            farthest: Vec::new(),
+            radius2: Vec::new(),
+        };
+        let (farthest, radius2) = excluded.candidate(initial, &voronoi.cells.points[0], &voronoi.haussdorf);
+        excluded.farthest.push(farthest);
+        excluded.radius2.push(radius2);
+        voronoi.excluded = Some(excluded);
+
+        return Ok(voronoi);
+    }
+
+    /// Create a new decomposer for the given `points`, using the Euclidean
+    /// distance, with all the points in `initial` already selected. The
+    /// following calls to `select` or `next_candidate` then find the points
+    /// farthest from all the initial points.
+    ///
+    /// This returns an error if `initial` is empty or contains indexes out of
+    /// bounds, or if one of the distances to the initial points is not finite.
+    pub fn new_multi(points: CowArray<'a, T, Ix2>, initial: &[usize]) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        let n_points = points.nrows();
+        if let Some(&index) = initial.iter().find(|&&index| index >= n_points) {
+            return Err(VoronoiError::IndexOutOfBounds { index, n_points });
+        }
+
+        let (&first, others) = initial.split_first().ok_or(VoronoiError::EmptySelection)?;
+        let mut voronoi = VoronoiDecomposer::try_new(points, first)?;
+        voronoi.reserve(others.len());
+        voronoi.insert_points(others.iter().copied())?;
+
+        return Ok(voronoi);
+    }
+
+    /// Create a new decomposer selecting points from `pool` which are far
+    /// from all the `reference` points (for example an existing training
+    /// set), using the Euclidean distance.
+    ///
+    /// The distance of each pool point to the selection starts as the
+    /// distance to its nearest reference point. If `initial` is `None`, the
+    /// first point selected by `select` or `next_candidate` is the pool point
+    /// farthest from all the reference points; otherwise the pool point at
+    /// index `initial` is selected first. The following points maximize the
+    /// distance to both the reference and the selected points.
+    ///
+    /// `selected()`, `selected_mask()`, `assignments()` and `len()` only
+    /// contain the pool points. The reference points are still the centers of
+    /// the first `reference.nrows()` cells, and the values in `assignments()`
+    /// and the positions returned by `nearest_center` are indexes in
+    /// `cells()`, including these reference cells.
+    ///
+    /// This copies both sets of points, and returns an error if `reference`
+    /// is empty, if the points have a different number of features, or if
+    /// `initial` is out of bounds. The resulting decomposer can not be
+    /// extended with new points.
+    pub fn new_with_reference(
+        pool: ArrayView2<'_, T>,
+        reference: ArrayView2<'_, T>,
+        initial: Option<usize>,
+    ) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        if reference.ncols() != pool.ncols() {
+            return Err(VoronoiError::InvalidDimension { expected: pool.ncols(), got: reference.ncols() });
+        }
+
+        let n_points = pool.nrows();
+        if let Some(initial) = initial.filter(|&initial| initial >= n_points) {
+            return Err(VoronoiError::IndexOutOfBounds { index: initial, n_points });
+        }
+
+        let points = ndarray::concatenate(ndarray::Axis(0), &[pool.view(), reference.view()]).expect("the shapes should match");
+        let reference = (n_points..points.nrows()).collect::<Vec<_>>();
+        let mut voronoi = VoronoiDecomposer::new_multi(points.into(), &reference)?;
+        voronoi.n_reference = reference.len();
+        if let Some(initial) = initial {
+            voronoi.insert_points(std::iter::once(initial))?;
+        }
+
+        return Ok(voronoi);
+    }
+
+    /// Create a new decomposer for points described by multiple sets of
+    /// features (views), for example descriptors of the same structures at
+    /// two levels of theory. `views` contains one array per view, all with one
+    /// row per point, and the point at index `initial` is the first selected
+    /// point. The selected indexes are shared by all views.
+    ///
+    /// The squared distance between two points is the sum over all views of
+    /// the squared Euclidean distance in this view, multiplied by the weight
+    /// of the view in `weights` (all weights are 1 if `weights` is `None`).
+    /// This is the same as using the Euclidean distance on the concatenated
+    /// features, with the features of each view scaled by `sqrt(weight)`,
+    /// without making a copy of the views.
+    ///
+    /// The new points for `extend_dataset` and the query vectors for
+    /// `nearest_center` contain the features of all views, concatenated in
+    /// the same order as in `views`.
+    ///
+    /// This returns an error if `views` is empty, if the views do not all
+    /// have the same number of rows, or if the weights are invalid (with the
+    /// index of the view for negative weights).
+    pub fn new_multi_view(views: &[ArrayView2<'a, T>], weights: Option<&[T]>, initial: usize) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        if views.is_empty() {
+            return Err(VoronoiError::EmptyDataset);
+        }
+
+        let n_points = views[0].nrows();
+        if let Some((view, points)) = views.iter().enumerate().find(|(_, points)| points.nrows() != n_points) {
+            return Err(VoronoiError::InvalidView { view, expected: n_points, got: points.nrows() });
+        }
+
+        let weights = match weights {
+            Some(weights) => weights.to_vec(),
+            None => vec![T::one(); views.len()],
+        };
+        if weights.len() != views.len() {
+            return Err(VoronoiError::InvalidWeights { expected: views.len(), got: weights.len() });
+        }
+        if let Some(index) = weights.iter().position(|&w| w < T::zero() || w.is_nan()) {
+            return Err(VoronoiError::NegativeWeight { index });
+        }
+
+        let views = views.iter().map(|&view| view.into()).collect();
+        let distances = MultiViewDistances::new(views, weights);
+        return VoronoiDecomposer::try_from_distances(Box::new(distances), initial);
+    }
+
+    /// Create a new decomposer for the given `points`, using the Euclidean
+    /// distance, and resuming from a previous selection. The first point in
+    /// `selected` is used as the initial point, and all the other points are
+    /// added in order.
+    ///
+    /// This is the same as [`VoronoiDecomposer::new_multi`], and returns an
+    /// error if `selected` is empty or contains indexes out of bounds, or if
+    /// one of the distances to the selected points is not finite.
+    pub fn new_from_selection(points: CowArray<'a, T, Ix2>, selected: &[usize]) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        return VoronoiDecomposer::new_multi(points, selected);
+    }
+
+    /// Create a new decomposer for the given `points`, using the cosine
+    /// distance `d^2(a, b) = 2 (1 - cos θ)`. The point at index `initial` is
+    /// the first selected point.
+    ///
+    /// The points are normalized before running the selection, which
+    /// requires a copy of the data. The cosine distance is then the Euclidean
+    /// distance between normalized points, and the Voronoï pruning remains
+    /// valid. This returns an error if one of the points is the zero vector.
+    pub fn new_cosine(points: CowArray<'a, T, Ix2>, initial: usize) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        let distances = EuclideanDistances::new_normalized(points)?;
+        return VoronoiDecomposer::try_from_distances(Box::new(distances), initial);
+    }
+
+    /// Create a new decomposer for the given `points`, using a custom
+    /// `metric` to compute distances. The point at index `initial` is the
+    /// first selected point.
+    ///
+    /// The Voronoï decomposition relies on the triangle inequality, so the
+    /// square root of `metric.distance2` must be a proper metric. This is the
+    /// case for all the metrics in [`crate::metric`].
+    ///
+    /// # Panics
+    ///
+    /// In the same cases as [`VoronoiDecomposer::new`], or if the points do not
+    /// have the number of dimensions required by the metric (see
+    /// [`Metric::dimension`]).
+    pub fn new_with_metric<M: Metric<T> + 'a>(
+        points: CowArray<'a, T, Ix2>,
+        initial: usize,
+        metric: M,
+    ) -> VoronoiDecomposer<'a, T> {
+        return VoronoiDecomposer::try_new_with_metric(points, initial, metric).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Same as [`VoronoiDecomposer::new_with_metric`], but returns an error
+    /// instead of panicking.
+    pub fn try_new_with_metric<M: Metric<T> + 'a>(
+        points: CowArray<'a, T, Ix2>,
+        initial: usize,
+        metric: M,
+    ) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        if let Some(expected) = metric.dimension() {
+            if points.ncols() != expected {
+                return Err(VoronoiError::InvalidDimension { expected: expected, got: points.ncols() });
+            }
+        }
+
+        let distances = MetricDistances::new(points, metric);
+        return VoronoiDecomposer::try_from_distances(Box::new(distances), initial);
+    }
+
+    /// Create a new decomposer in the feature space induced by a kernel, from
+    /// the Gram matrix `kernel` between all points. The point at index
+    /// `initial` is the first selected point.
+    ///
+    /// The distances are computed as `d^2(i, j) = K(i, i) + K(j, j) - 2 K(i,
+    /// j)`. This returns an error if the kernel matrix is not square or not
+    /// symmetric (up to a small relative tolerance).
+    pub fn from_gram(kernel: ArrayView2<'a, T>, initial: usize) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        let tolerance = T::from(100.0).expect("failed to convert tolerance") * T::epsilon();
+
+        if kernel.nrows() != kernel.ncols() {
+            return Err(VoronoiError::NotSquare { rows: kernel.nrows(), columns: kernel.ncols() });
+        }
+
+        for i in 0..kernel.nrows() {
+            for j in (i + 1)..kernel.ncols() {
+                let (kij, kji) = (kernel[[i, j]], kernel[[j, i]]);
+                if (kij - kji).abs() > tolerance * T::max(kij.abs(), kji.abs()) {
+                    return Err(VoronoiError::NotSymmetric { i, j });
+                }
+            }
+        }
+
+        let distances = GramDistances::new(kernel.into());
+        return VoronoiDecomposer::try_from_distances(Box::new(distances), initial);
+    }
+
+    /// Create a new decomposer for points stored as a sparse matrix in CSR
+    /// format with `n_features` columns, using Euclidean distances. The
+    /// point at index `initial` is the first selected point.
+    ///
+    /// The non-zero values of row `i` are `data[indptr[i]..indptr[i + 1]]`,
+    /// in the columns `indices[indptr[i]..indptr[i + 1]]`, which must be
+    /// sorted (as done by `scipy.sparse.csr_matrix.sort_indices`). Distances
+    /// are computed from the dot product of the rows, only going through the
+    /// non-zero values.
+    pub fn from_sparse(
+        n_features: usize,
+        indptr: &'a [usize],
+        indices: &'a [usize],
+        data: &'a [T],
+        initial: usize,
+    ) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        let distances = SparseDistances::new(n_features, indptr, indices, data)?;
+        return VoronoiDecomposer::try_from_distances(Box::new(distances), initial);
+    }
+
     #[cfg_attr(feature = "time-graph", time_graph::instrument(name = "initialize voronoi"))]
-    pub fn new(points: CowArray<'a, f64, Ix2>, initial: usize) -> VoronoiDecomposer<'a> {
-        let norms = points.axis_iter(Axis(0))
-            .map(|row| row.dot(&row))
-            .collect::<Array1<f64>>();
-        let center = points.slice(s![initial, ..]);
-        let haussdorf = &norms + norms[initial] - 2.0 * center.dot(&points.t());
-
-        let mut cells = VoronoiCellVec::new();
-        let (farthest, radius2) = find_max(haussdorf.iter());
-        cells.push(VoronoiCell {
-            center_idx: initial,
-            center: center.to_owned(),
-            farthest: farthest,
-            radius2: radius2,
-            points: (0..points.shape()[0]).collect()
-        });
+    fn try_from_distances(mut distances: Box<dyn Distances<T> + 'a>, initial: usize) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+
+        let n_points = distances.n_points();
+        if n_points == 0 {
+            return Err(VoronoiError::EmptyDataset);
+        }
+
+        if initial >= n_points {
+            return Err(VoronoiError::IndexOutOfBounds { index: initial, n_points });
+        }
+
+        let haussdorf = into_par_iter!(0..n_points)
+            .map(|point| distances.distance2(initial, point))
+            .collect::<Vec<_>>();
+
+        if let Some(index) = haussdorf.iter().position(|d2| d2.is_nan()) {
+            return Err(VoronoiError::NaNDistance { index });
+        }
 
-        VoronoiDecomposer {
-            points: points,
+        if let Some(j) = haussdorf.iter().position(|d2| d2.is_infinite()) {
+            return Err(VoronoiError::NonFiniteDistance { i: initial, j });
+        }
+
+        distances.push_center(initial);
+        let mut cells = VoronoiCells::new();
+        // the points are compared with the same total order as the cells in
+        // `best_cell`, giving the same farthest point with any number of threads
+        let points = into_par_iter!(0..n_points).map(|point| (point, haussdorf[point], point));
+        let (farthest, radius2, _) = reduce_with!(points, best_candidate).expect("there should be points");
+        cells.push(initial, farthest, radius2, (0..n_points).collect());
+        log_debug!("created a Voronoï decomposer for {} points, starting from point {}", n_points, initial);
+
+        return Ok(VoronoiDecomposer {
+            distances: distances,
             cells: cells,
-            norms: norms.to_vec(),
-            haussdorf: haussdorf.to_vec(),
+            haussdorf: haussdorf,
+            assignments: vec![0; n_points],
+            initial_radius2: radius2,
+            min_center_distance2: T::infinity(),
+            last_recompute_count: n_points,
+            weights: None,
+            excluded: None,
+            cutoff: None,
+            min_separation2: T::zero(),
+            n_reference: 0,
+            trace: None,
+            #[cfg(feature = "metrics")]
+            timings: std::sync::Mutex::new(Timings {
+                construction: start.elapsed(),
+                ..Default::default()
+            }),
+            block_size: DEFAULT_BLOCK_SIZE,
             work: WorkArrays::new(),
+        });
+    }
+
+    /// Set the number of points processed together when computing the
+    /// distances to a newly selected point (default: 64). Each block is
+    /// processed by a single thread, keeping the new point in cache while
+    /// streaming over the points of the block. This does not change the
+    /// selection.
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is zero.
+    pub fn set_block_size(&mut self, block_size: usize) {
+        assert!(block_size > 0, "the block size must be positive");
+        self.block_size = block_size;
+    }
+
+    /// Use a maximal distance `cutoff` for the selection. The selection then
+    /// stops once all points are within `cutoff` of a selected point, and the
+    /// isolated points (farther than `cutoff` from all other points) are
+    /// treated as outliers instead of being selected.
+    ///
+    /// The outliers are found lazily by `select` and the other selection
+    /// functions, when they would be the next selected point: they are then
+    /// excluded from the selection (see [`VoronoiDecomposer::new_with_excluded`])
+    /// and recorded in `outliers()`. `next_candidate` does not check if the
+    /// candidate is an outlier, but returns `None` once all the cells have a
+    /// radius below the cutoff.
+    ///
+    /// Setting a new cutoff keeps the outliers found previously. The cutoff
+    /// is not part of the saved state of the decomposer.
+    ///
+    /// # Panics
+    ///
+    /// If `cutoff` is negative or NaN.
+    pub fn set_cutoff(&mut self, cutoff: T) {
+        assert!(cutoff >= T::zero(), "the cutoff can not be negative");
+        let outliers = match self.cutoff.take() {
+            Some(previous) => previous.outliers,
+            None => Vec::new(),
+        };
+        self.cutoff = Some(Cutoff {
+            cutoff2: cutoff * cutoff,
+            outliers: outliers,
+        });
+    }
+
+    /// Forbid selecting points closer than `min_separation2` (squared
+    /// distance) to any already selected point. The selection functions then
+    /// skip the candidates closer than this to the center of their cell, and
+    /// stop when no candidate satisfies the constraint. Points added manually
+    /// with `add_point` are not checked.
+    ///
+    /// Without weights, the next candidate is the point farthest from all
+    /// selected points, so this stops the selection once the largest squared
+    /// radius is below `min_separation2`, like `select_until`. For weighted
+    /// decomposers, the candidates of cells with a high weight but a small
+    /// radius are skipped in favor of the next best candidates. Combined with
+    /// `select_until` or a cutoff from `set_cutoff`, the selection stops as
+    /// soon as one of the criteria is met.
+    ///
+    /// # Panics
+    ///
+    /// If `min_separation2` is negative or NaN.
+    pub fn set_min_separation2(&mut self, min_separation2: T) {
+        assert!(min_separation2 >= T::zero(), "the minimal separation can not be negative");
+        self.min_separation2 = min_separation2;
+    }
+
+    /// Get the points found to be outliers for the cutoff set with
+    /// `set_cutoff`, in the order they were found. These points are farther
+    /// than the cutoff from all the other points, and are skipped by the
+    /// selection.
+    pub fn outliers(&self) -> &[usize] {
+        match self.cutoff {
+            Some(ref cutoff) => &cutoff.outliers,
+            None => &[],
+        }
+    }
+
+    /// Get the time spent in each phase of the selection since the
+    /// decomposer was created, or since the last call to this function.
+    #[cfg(feature = "metrics")]
+    pub fn take_timings(&mut self) -> Timings {
+        let timings = self.timings.get_mut().expect("poisoned mutex");
+        return std::mem::take(timings);
+    }
+
+    /// Start recording an [`AddPointEvent`] each time a point is added to the
+    /// decomposition. Use `take_trace` to get the recorded events.
+    pub fn enable_trace(&mut self) {
+        if self.trace.is_none() {
+            self.trace = Some(Vec::new());
+        }
+    }
+
+    /// Get all the events recorded since tracing was enabled or since the
+    /// last call to this function. This returns an empty vector if tracing
+    /// is not enabled.
+    pub fn take_trace(&mut self) -> Vec<AddPointEvent<T>> {
+        match self.trace {
+            Some(ref mut trace) => std::mem::take(trace),
+            None => Vec::new(),
         }
     }
 
+    /// Check if the point at index `point` is the center of its cell, i.e.
+    /// if it is already selected
+    fn is_center(&self, point: usize) -> bool {
+        return self.cells.center_idx[self.assignments[point]] == point;
+    }
+
     /// Allocate capacity for `additional` more cells/selected points
     pub fn reserve(&mut self, additional: usize) {
         self.cells.reserve(additional);
         self.work.reserve(additional);
     }
 
-    /// Add a new selected point as the center of a Voronoï cell
-    #[cfg_attr(feature = "time-graph", time_graph::instrument(name = "add new voronoi cell"))]
+    /// Add a new selected point as the center of a Voronoï cell. If the point
+    /// is already the center of a cell, this function does nothing.
+    ///
+    /// # Panics
+    ///
+    /// If `new_point` is out of bounds, or if the distance between
+    /// `new_point` and another point is NaN or infinite.
     pub fn add_point(&mut self, new_point: usize) {
+        self.add_points(std::iter::once(new_point));
+    }
+
+    /// Same as `add_point`, but returns an error instead of panicking if
+    /// `new_point` is out of bounds or if one of the new distances is NaN or
+    /// infinite, and instead of doing nothing if `new_point` is already
+    /// selected. The decomposer is unchanged if this returns an error.
+    pub fn try_add_point(&mut self, new_point: usize) -> Result<(), VoronoiError> {
+        let n_points = self.haussdorf.len() - self.n_reference;
+        if new_point >= n_points {
+            return Err(VoronoiError::IndexOutOfBounds { index: new_point, n_points });
+        }
+
+        if self.is_center(new_point) {
+            return Err(VoronoiError::AlreadySelected { index: new_point });
+        }
+
+        return self.insert_points(std::iter::once(new_point));
+    }
+
+    /// Add a group of points (for example all the environments in a
+    /// structure) as new selected points with `add_points`, and return the
+    /// largest squared radius of the cells once all of them are added.
+    pub fn add_group(&mut self, points: &[usize]) -> T {
+        self.add_points(points.iter().copied());
+        let (_, max_radius2) = find_max(self.cells.radius2.iter());
+        return max_radius2;
+    }
+
+    /// Add multiple new selected points as the centers of new Voronoï cells.
+    ///
+    /// This gives the same result as calling `add_point` for each point in
+    /// sequence, but only updates the radius and farthest point of the
+    /// modified cells once all points have been added. Points which are
+    /// already the center of a cell are ignored.
+    ///
+    /// # Panics
+    ///
+    /// If one of the points is out of bounds, or if the distance between one
+    /// of the points and another point is NaN or infinite. The points before
+    /// this one are still added.
+    pub fn add_points<I: IntoIterator<Item = usize>>(&mut self, points: I) {
+        if let Err(error) = self.insert_points(points) {
+            panic!("failed to add a new point: {}", error);
+        }
+    }
+
+    /// Implementation of `add_points`, stopping at the first point with
+    /// non-finite distances. All the points before this one are added.
+    #[cfg_attr(feature = "time-graph", time_graph::instrument(name = "add new voronoi cells"))]
+    fn insert_points<I: IntoIterator<Item = usize>>(&mut self, points: I) -> Result<(), VoronoiError> {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+
+        self.work.modified_cells.clear();
+        let mut result = Ok(());
+        for new_point in points {
+            if self.is_center(new_point) {
+                continue;
+            }
+
+            result = self.insert_cell(new_point);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.update_modified_cells();
+
+        // sanity check that all points are in the right place
+        for (points, &center_idx) in self.cells.points.iter().zip(&self.cells.center_idx) {
+            debug_assert!(!points.is_empty());
+            if points.len() == 1 {
+                debug_assert_eq!(points[0], center_idx);
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        {
+            let timings = self.timings.get_mut().expect("poisoned mutex");
+            timings.add_points += start.elapsed();
+            timings.n_add_points += 1;
+        }
+
+        return result;
+    }
+
+    /// Create a new cell centered on `new_point`, and move all the points
+    /// closer to `new_point` than to their current center in this new cell.
+    ///
+    /// The radius of the new cell is computed, but the radius and farthest
+    /// point of the cells which lost some points are not updated. Instead,
+    /// these cells are recorded in `self.work.modified_cells`, and must be
+    /// updated with `update_modified_cells`. The outdated radius of these
+    /// cells is still an upper bound on the actual radius, making it usable
+    /// to find active cells.
+    ///
+    /// If one of the new distances is not finite, this returns an error and
+    /// leaves the cells unchanged.
+    #[cfg_attr(feature = "time-graph", time_graph::instrument(name = "add new voronoi cell"))]
+    fn insert_cell(&mut self, new_point: usize) -> Result<(), VoronoiError> {
+        debug_assert!(!self.is_center(new_point), "point {} is already selected", new_point);
         self.work.clear();
+        let quarter = T::from(0.25).expect("failed to convert 0.25");
+
+        // position of the new cell in `self.cells`
+        let new_center = self.cells.center_idx.len();
+        self.distances.push_center(new_point);
+        let mut new_min_center_distance2 = self.min_center_distance2;
 
-        let new_center = self.points.slice(s![new_point, ..]);
         tracing_span!("find active cells", {
             // now we find the "active" Voronoi cells, i.e. those that might change
             // due to the new selection. We must compute distance of the new point
             // to all the previous FPS.
-            for (&center_idx, center) in soa_zip!(&self.cells, [center_idx, center]) {
-                let d2 = self.norms[new_point] + self.norms[center_idx] - 2.0 * new_center.dot(&center.view());
-                self.work.distance_to_new_point.push(d2);
+            self.distances.distances2_between_centers(
+                new_center, new_point, &self.cells.center_idx, &mut self.work.distance_to_new_point
+            );
+            let non_finite = self.work.distance_to_new_point.iter().position(|d2| !d2.is_finite());
+            if let Some(cell_idx) = non_finite {
+                self.distances.pop_center();
+                let d2 = self.work.distance_to_new_point[cell_idx];
+                return Err(non_finite_error(new_point, self.cells.center_idx[cell_idx], d2));
+            }
+
+            for &d2 in &self.work.distance_to_new_point {
+                new_min_center_distance2 = new_min_center_distance2.min(d2);
             }
 
             for (cell_idx, &radius2) in self.cells.radius2.iter().enumerate() {
                 // triangle inequality (r > d / 2), squared
-                if 0.25 * self.work.distance_to_new_point[cell_idx] < radius2 {
+                if quarter * self.work.distance_to_new_point[cell_idx] < radius2 {
                     self.work.active_cells.insert(cell_idx);
                 }
             }
 
-            for &cell_idx in &self.work.active_cells {
-                self.cells.radius2[cell_idx] = 0.0;
-                self.cells.farthest[cell_idx] = self.cells.center_idx[cell_idx];
-            }
+            self.work.modified_cells.extend(&self.work.active_cells);
         });
 
-        let mut new_cell = VoronoiCell {
-            center: new_center.to_owned(),
-            center_idx: new_point,
-            // these will be updated below,
-            radius2: 0.0,
-            farthest: new_point,
-            points: Vec::new(),
-        };
-
-        // use a channel to communicate the points that need to be added to the
-        // new cell.
-        let (new_cell_points_sender, new_cell_points_receiver) = std::sync::mpsc::channel();
-        let new_farthest_point = ThreadLocal::new();
-
-        let points = &self.points;
-        let norms = &self.norms;
+        let distances = &self.distances;
         let work = &self.work;
         let all_haussdorf = &self.haussdorf;
+        let block_size = self.block_size;
 
-        tracing_span!("update decomposition", {
-            self.cells.points
-                .par_iter_mut()
-                .zip_eq(&mut self.cells.radius2)
-                .zip_eq(&mut self.cells.farthest)
+        let updated_cells = tracing_span!("update decomposition", {
+            par_iter!(self.cells.points)
                 .enumerate()
-                .filter_map(|(cell_idx, ((points_idx, radius2), farthest))| {
-                    if work.active_cells.contains(&cell_idx) {
-                        Some((cell_idx, points_idx, radius2, farthest))
-                    } else {
-                        None
-                    }
-                })
-                .for_each_with(new_cell_points_sender, |sender, (cell_idx, points_idx, radius2, farthest)| {
-                    let mut cell_updated_points = Vec::new();
-                    // farthest point found on this thread
-                    let farthest_point = new_farthest_point.get_or(FarthestPoint::default);
-
-                    for &point in &*points_idx {
-                        let haussdorf = all_haussdorf[point];
-
+                .filter(|(cell_idx, _)| work.active_cells.contains(cell_idx))
+                .map(|(cell_idx, points_idx)| {
+                    // distance to the new point for the points that need to
+                    // be assigned to the new cell, computed by blocks of
+                    // points to keep the new point in cache
+                    let new_distances = par_chunks!(points_idx, block_size).map(|block| {
                         // Check if we can skip this check for this point. This is a
                         // tighter bound on the distance, since ||x_j - x_new|| <
                         // new_radius
-                        if 0.25 * work.distance_to_new_point[cell_idx] < haussdorf {
-                            let d2 = norms[new_point] + norms[point] - 2.0 * new_center.dot(&points.slice(s![point, ..]));
-                            if haussdorf > d2 {
-                                // We assign this point to the new cell
-                                sender.send((point, d2)).expect("failed to send new point");
-
-                                if d2 > farthest_point.distance2.get() {
-                                    farthest_point.distance2.set(d2);
-                                    farthest_point.index.set(point);
-                                }
+                        let needs_distance = |point: usize| {
+                            point == new_point || quarter * work.distance_to_new_point[cell_idx] < all_haussdorf[point]
+                        };
 
-                                continue;
+                        let mut candidates = block.iter().copied().filter(|&point| needs_distance(point)).collect::<Vec<_>>();
+                        distances.prefilter(new_center, new_point, &mut candidates, all_haussdorf);
+
+                        let mut candidates_d2 = Vec::with_capacity(candidates.len());
+                        distances.center_distances2(new_center, new_point, &candidates, &mut candidates_d2);
+                        let non_finite = candidates.iter().zip(&candidates_d2)
+                            .find(|(_, d2)| !d2.is_finite())
+                            .map(|(&point, &d2)| (point, d2));
+                        let mut candidates_d2 = candidates.iter().copied().zip(candidates_d2).peekable();
+
+                        let block_distances = block.iter().map(|&point| {
+                            if let Some((_, d2)) = candidates_d2.next_if(|&(candidate, _)| candidate == point) {
+                                if point == new_point {
+                                    // the computed distance of the new point to
+                                    // itself can be slightly above zero due to
+                                    // rounding errors
+                                    return Some(T::zero());
+                                } else if all_haussdorf[point] > d2 {
+                                    return Some(d2);
+                                }
                             }
-                        }
+                            return None;
+                        }).collect::<Vec<_>>();
 
-                        // the point is still in the same cell, make sure to
-                        // update the cell radius/farthest point if needed
-                        cell_updated_points.push(point);
-                        if haussdorf > *radius2 {
-                            *radius2 = haussdorf;
-                            *farthest = point;
+                        return (block_distances, candidates.len(), non_finite);
+                    }).collect::<Vec<_>>();
+
+                    let mut moved_points = Vec::new();
+                    let mut cell_updated_points = Vec::new();
+                    let mut n_distances = 0;
+                    let mut non_finite = None;
+                    let new_distances = new_distances.into_iter().flat_map(|(block_distances, count, block_non_finite)| {
+                        n_distances += count;
+                        non_finite = non_finite.or(block_non_finite);
+                        block_distances
+                    });
+                    for (&point, d2) in points_idx.iter().zip(new_distances) {
+                        match d2 {
+                            Some(d2) => moved_points.push((point, d2)),
+                            // the point is still in the same cell
+                            None => cell_updated_points.push(point),
                         }
                     }
 
-                    std::mem::swap(points_idx, &mut cell_updated_points);
-                });
-
-                for (point, haussdorf) in new_cell_points_receiver.iter() {
-                    new_cell.points.push(point);
-                    self.haussdorf[point] = haussdorf;
-                }
+                    return (cell_idx, moved_points, cell_updated_points, n_distances, non_finite);
+                })
+                .collect::<Vec<_>>()
         });
 
-        for farthest in new_farthest_point.into_iter() {
-            if farthest.distance2.get() > new_cell.radius2 {
-                new_cell.radius2 = farthest.distance2.get();
-                new_cell.farthest = farthest.index.get();
-            }
+        let non_finite = updated_cells.iter().find_map(|&(_, _, _, _, non_finite)| non_finite);
+        if let Some((point, d2)) = non_finite {
+            self.distances.pop_center();
+            return Err(non_finite_error(new_point, point, d2));
+        }
+
+        self.min_center_distance2 = new_min_center_distance2;
+        let mut moved_points = Vec::with_capacity(updated_cells.len());
+        for (cell_idx, cell_moved_points, cell_updated_points, n_distances, _) in updated_cells {
+            self.cells.points[cell_idx] = cell_updated_points;
+            moved_points.push((cell_idx, cell_moved_points, n_distances));
         }
 
-        self.cells.push(new_cell);
+        self.last_recompute_count = moved_points.iter().map(|&(_, _, n)| n).sum();
+        log_trace!(
+            "selected point {} at radius2 {:?}, computed {} distances, moved {} points",
+            new_point,
+            self.haussdorf[new_point],
+            self.last_recompute_count,
+            moved_points.iter().map(|(_, points, _)| points.len()).sum::<usize>()
+        );
 
-        // sanity check that all points are in the right place
-        for cell in &self.cells {
-            debug_assert!(!cell.points.is_empty());
-            if cell.points.len() == 1 {
-                debug_assert_eq!(cell.points[0], *cell.center_idx);
+        if let Some(ref mut trace) = self.trace {
+            let source_cells = moved_points.iter()
+                .filter(|(_, points, _)| !points.is_empty())
+                .map(|&(cell_idx, _, _)| cell_idx)
+                .collect();
+
+            trace.push(AddPointEvent {
+                point: new_point,
+                radius2: self.haussdorf[new_point],
+                n_reassigned: moved_points.iter().map(|(_, points, _)| points.len()).sum(),
+                source_cells: source_cells,
+                n_distances: self.cells.center_idx.len() + self.last_recompute_count,
+            });
+        }
+
+        // the moved points are always in the same order (sorted by cell, and
+        // then by position in the cell), making the selection independent of
+        // the number of threads
+        let mut new_cell_radius2 = T::zero();
+        let mut new_cell_farthest = new_point;
+        let mut new_cell_points = Vec::new();
+        let new_cell_idx = self.cells.center_idx.len();
+        for (point, haussdorf) in moved_points.into_iter().flat_map(|(_, points, _)| points) {
+            new_cell_points.push(point);
+            self.haussdorf[point] = haussdorf;
+            self.assignments[point] = new_cell_idx;
+
+            if haussdorf > new_cell_radius2 || (haussdorf == new_cell_radius2 && point < new_cell_farthest) {
+                new_cell_radius2 = haussdorf;
+                new_cell_farthest = point;
             }
         }
+
+        self.cells.push(new_point, new_cell_farthest, new_cell_radius2, new_cell_points);
+        return Ok(());
     }
 
-    /// Access the current list of cells
-    pub fn cells(&self) -> VoronoiCellSlice {
-        self.cells.as_slice()
+    /// Remove the most recently selected point, and go back to the state
+    /// before it was added. This returns the index of the removed point, or
+    /// `None` if only the initial point (or only the reference points) is
+    /// selected.
+    ///
+    /// All the points in the cell of the removed point are re-assigned to
+    /// their closest remaining selected point, which costs one distance
+    /// computation per remaining selected point for each of these points.
+    pub fn remove_last(&mut self) -> Option<usize> {
+        let n_cells = self.cells.center_idx.len();
+        if n_cells < 2 || self.is_empty() {
+            return None;
+        }
+
+        let orphans = self.cells.points.last().expect("there should be cells");
+        let nearest = self.nearest_cells(orphans, n_cells - 1)
+            .expect("the distances to the remaining selected points should be finite");
+
+        let (removed, orphans) = self.cells.pop().expect("there should be cells");
+        self.distances.pop_center();
+        self.assign_to_cells(&orphans, nearest);
+        self.min_center_distance2 = self.compute_min_center_distance2();
+
+        return Some(removed);
     }
 
-    /// Get the potential next point, i.e. the point with highest Haussdorf distance
-    pub fn next_point(&self) -> (usize, f64) {
-        let (max_radius_cell, radius) = find_max(self.cells.radius2.iter());
-        return (self.cells.farthest[max_radius_cell], radius);
+    /// Compute the smallest distance between two selected points from
+    /// scratch, with one distance computation for each pair of points
+    fn compute_min_center_distance2(&self) -> T {
+        let centers = &self.cells.center_idx;
+        let mut min = T::infinity();
+        for (i, &center_i) in centers.iter().enumerate() {
+            for &center_j in &centers[i + 1..] {
+                min = min.min(self.distances.distance2(center_i, center_j));
+            }
+        }
+        return min;
     }
-}
 
-/// Select `n_select` points from `points` using Farthest Points Sampling, and
-/// return the indexes of selected points. The first point (already selected) is
-/// the point at the `initial` index.
-#[cfg_attr(feature = "time-graph", time_graph::instrument)]
-pub fn select_fps(points: ArrayView2<'_, f64>, n_select: usize, initial: usize) -> Vec<usize> {
-    let n_points = points.nrows();
+    /// Add new points to the dataset, after the existing ones. The new points
+    /// are assigned to the cell of their closest selected point, and are then
+    /// candidates for the next selections.
+    ///
+    /// The decomposer needs to own the points to extend them, so this copies
+    /// the existing points if they were borrowed. This returns an error if
+    /// the new points do not have the right number of features, if the
+    /// distance between a new point and a selected point is NaN or infinite,
+    /// for decomposers created from a Gram matrix, for weighted decomposers,
+    /// or for decomposers with reference points. The decomposer is unchanged
+    /// if this returns an error.
+    pub fn extend_dataset(&mut self, new_points: ArrayView2<'_, T>) -> Result<(), VoronoiError> {
+        if self.weights.is_some() || self.n_reference != 0 {
+            return Err(VoronoiError::CanNotExtend);
+        }
 
-    if n_select > n_points {
-        panic!("can not select more points than what we have")
-    }
+        let first = self.distances.n_points();
+        self.distances.extend(new_points)?;
 
-    let mut voronoi = VoronoiDecomposer::new(points.into(), initial);
-    voronoi.reserve(n_select - 1);
-
-    for _ in 1..n_select {
-        // Find the maximum minimum (maxmin) distance and the corresponding
-        // point. The maxmin point must be one of the farthest points from the
-        // Voronoï decomposition, so we only have to look at the list of
-        // existing cells to find it.
-        let new_point = {
-            let cells = voronoi.cells();
-            let (max_radius_cell, _) = find_max(cells.radius2.iter());
-            cells.farthest[max_radius_cell]
+        let added = (first..self.distances.n_points()).collect::<Vec<_>>();
+        let nearest = match self.nearest_cells(&added, self.cells.center_idx.len()) {
+            Ok(nearest) => nearest,
+            Err(error) => {
+                self.distances.truncate(first);
+                return Err(error);
+            }
         };
-        voronoi.add_point(new_point);
-    }
 
-    return voronoi.cells().center_idx.to_owned();
-}
+        self.haussdorf.resize(self.distances.n_points(), T::zero());
+        self.assignments.resize(self.distances.n_points(), 0);
+        if let Some(ref mut excluded) = self.excluded {
+            // new points can always be selected
+            excluded.mask.resize(self.distances.n_points(), false);
+        }
+        self.assign_to_cells(&added, nearest);
+        log_debug!("added {} points to the Voronoï decomposer, {} points in total", added.len(), self.distances.n_points());
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        // the new points might be close to the outliers, which have to be
+        // checked again
+        let outliers = match self.cutoff {
+            Some(ref mut cutoff) => std::mem::take(&mut cutoff.outliers),
+            None => Vec::new(),
+        };
+        for point in outliers {
+            let excluded = self.excluded.as_mut().expect("outliers should be excluded");
+            excluded.mask[point] = false;
+            self.update_candidate(self.assignments[point]);
+        }
 
-    use ndarray::Array2;
+        return Ok(());
+    }
 
-    use ndarray_npy::read_npy;
-    use std::path::PathBuf;
+    /// Find the closest cell center among the first `n_cells` cells for all
+    /// the `points`, together with the squared distance to this center. This
+    /// costs one distance computation per selected point for each point, and
+    /// returns an error if one of the distances is not finite.
+    fn nearest_cells(&self, points: &[usize], n_cells: usize) -> Result<Vec<(usize, T)>, VoronoiError> {
+        let distances = &self.distances;
+        let centers = &self.cells.center_idx[..n_cells];
+        let nearest = par_iter!(points).map(|&point| {
+            let mut nearest = (0, T::infinity());
+            for (cell_idx, &center_idx) in centers.iter().enumerate() {
+                let d2 = distances.distance2(center_idx, point);
+                if !d2.is_finite() {
+                    return Err(non_finite_error(center_idx, point, d2));
+                }
 
-    #[test]
-    fn check_simple() {
-        let data = Array2::from_shape_vec((4, 2), vec![
-            0.0, 1.0,
-            0.8, 0.5,
-            0.0, 0.0,
-            1.0, 0.0,
-        ]).unwrap();
+                if d2 < nearest.1 {
+                    nearest = (cell_idx, d2);
+                }
+            }
+            return Ok(nearest);
+        }).collect::<Vec<_>>();
 
-        let expected = vec![0, 3, 2, 1];
-        for n_select in 1..expected.len() {
-            let selected = select_fps(data.view(), n_select, expected[0]);
-            assert_eq!(selected, expected[..n_select]);
-        }
+        // report the error for the first point, independently of the number
+        // of threads
+        return nearest.into_iter().collect();
     }
 
-    #[test]
-    fn check_boston() {
+    /// Add the `points` to the cells given by `nearest`, as returned by
+    /// `nearest_cells`, and update the radius of the cells accordingly.
+    fn assign_to_cells(&mut self, points: &[usize], nearest: Vec<(usize, T)>) {
+        self.work.modified_cells.clear();
+        for (&point, (cell_idx, d2)) in points.iter().zip(nearest) {
+            self.haussdorf[point] = d2;
+            self.assignments[point] = cell_idx;
+            self.cells.points[cell_idx].push(point);
+            self.work.modified_cells.insert(cell_idx);
+        }
+
+        self.update_modified_cells();
+    }
+
+    /// Update the farthest point which is not excluded in the cell at
+    /// `cell_idx`, if there are excluded points
+    fn update_candidate(&mut self, cell_idx: usize) {
+        if let Some(ref mut excluded) = self.excluded {
+            let candidate = excluded.candidate(self.cells.center_idx[cell_idx], &self.cells.points[cell_idx], &self.haussdorf);
+            excluded.farthest[cell_idx] = candidate.0;
+            excluded.radius2[cell_idx] = candidate.1;
+        }
+    }
+
+    /// Update the radius and farthest point of all the cells in
+    /// `self.work.modified_cells`
+    #[cfg_attr(feature = "time-graph", time_graph::instrument(name = "update modified cells"))]
+    fn update_modified_cells(&mut self) {
+        let haussdorf = &self.haussdorf;
+        let modified_cells = &self.work.modified_cells;
+
+        par_iter!(self.cells.points)
+            .zip(&mut self.cells.radius2)
+            .zip(&mut self.cells.farthest)
+            .zip(&self.cells.center_idx)
+            .enumerate()
+            .filter(|(cell_idx, _)| modified_cells.contains(cell_idx))
+            .for_each(|(_, (((points_idx, radius2), farthest), &center_idx))| {
+                *radius2 = T::zero();
+                *farthest = center_idx;
+                for &point in points_idx {
+                    if haussdorf[point] > *radius2 || (haussdorf[point] == *radius2 && point < *farthest) {
+                        *radius2 = haussdorf[point];
+                        *farthest = point;
+                    }
+                }
+            });
+
+        if let Some(ref mut excluded) = self.excluded {
+            // update the modified cells, and add the new ones
+            let n_cells = self.cells.center_idx.len();
+            excluded.farthest.truncate(n_cells);
+            excluded.radius2.truncate(n_cells);
+            for &cell_idx in modified_cells {
+                if cell_idx < excluded.farthest.len() {
+                    let candidate = excluded.candidate(self.cells.center_idx[cell_idx], &self.cells.points[cell_idx], haussdorf);
+                    excluded.farthest[cell_idx] = candidate.0;
+                    excluded.radius2[cell_idx] = candidate.1;
+                }
+            }
+
+            for cell_idx in excluded.farthest.len()..n_cells {
+                let candidate = excluded.candidate(self.cells.center_idx[cell_idx], &self.cells.points[cell_idx], haussdorf);
+                excluded.farthest.push(candidate.0);
+                excluded.radius2.push(candidate.1);
+            }
+        }
+
+        if cfg!(debug_assertions) {
+            for &cell_idx in &self.work.modified_cells {
+                self.check_cell(cell_idx);
+            }
+        }
+    }
+
+    /// Check that the farthest point of the cell at `cell_idx` belongs to
+    /// this cell and is at `radius2` from the center, and that all points of
+    /// the cell are at most at `radius2` from the center.
+    fn check_cell(&self, cell_idx: usize) {
+        let farthest = self.cells.farthest[cell_idx];
+        let radius2 = self.cells.radius2[cell_idx];
+        let points = &self.cells.points[cell_idx];
+
+        debug_assert!(points.contains(&farthest), "farthest point {} is not in cell {}", farthest, cell_idx);
+        debug_assert!(self.haussdorf[farthest] == radius2, "wrong radius for cell {}", cell_idx);
+        debug_assert!(points.iter().all(|&point| {
+            self.assignments[point] == cell_idx && self.haussdorf[point] <= radius2
+        }), "invalid point in cell {}", cell_idx);
+    }
+
+    /// Access the current list of cells
+    pub fn cells(&self) -> VoronoiCellSlice<'_, T> {
+        self.cells.as_slice()
+    }
+
+    /// Get an owned copy of the current center, farthest point and radius of
+    /// all cells. Unlike `cells()`, this does not borrow the decomposer, and
+    /// can be kept while adding new points.
+    pub fn snapshot_cells(&self) -> CellsSnapshot<T> {
+        CellsSnapshot {
+            center_idx: self.cells.center_idx.clone(),
+            farthest: self.cells.farthest.clone(),
+            radius2: self.cells.radius2.clone(),
+        }
+    }
+
+    /// Get the center, farthest point and radius of all cells as a list of
+    /// `CellInfo`, in selection order. This is easier to sort or filter than
+    /// the separate arrays of `cells()`, at the cost of a copy.
+    pub fn cell_infos(&self) -> Vec<CellInfo<T>> {
+        let cells = &self.cells;
+        return cells.center_idx.iter()
+            .zip(&cells.farthest)
+            .zip(&cells.radius2)
+            .map(|((&center, &farthest), &radius2)| CellInfo { center, farthest, radius2 })
+            .collect();
+    }
+
+    /// Get a summary of how well the currently selected points cover the
+    /// full dataset
+    pub fn coverage(&self) -> Coverage<T> {
+        let (_, max_radius2) = find_max(self.cells.radius2.iter());
+
+        let haussdorf = self.nearest_distances2();
+        let n_points = T::from(haussdorf.len()).expect("failed to convert number of points");
+        let mean_distance2 = haussdorf.iter().copied().sum::<T>() / n_points;
+
+        let mut cell_sizes = BTreeMap::new();
+        for points in &self.cells.points {
+            *cell_sizes.entry(points.len()).or_insert(0) += 1;
+        }
+
+        let quantiles = self.distance2_quantiles(&[0.5, 0.9, 0.99]);
+
+        return Coverage {
+            max_radius2: max_radius2,
+            mean_distance2: mean_distance2,
+            quantiles_distance2: [quantiles[0], quantiles[1], quantiles[2]],
+            cell_sizes: cell_sizes,
+        };
+    }
+
+    /// Get the given `quantiles` (between 0 and 1) of the squared distance
+    /// from all points to the closest selected point. The values are
+    /// linearly interpolated between the two closest distances, like the
+    /// default method of `numpy.quantile`.
+    ///
+    /// This is less sensitive to outliers than the largest radius. It sorts a
+    /// copy of the distances, costing `O(n log n)` for `n` points.
+    ///
+    /// # Panics
+    ///
+    /// If one of the quantiles is not between 0 and 1.
+    pub fn distance2_quantiles(&self, quantiles: &[f64]) -> Vec<T> {
+        let mut sorted = self.nearest_distances2().into_owned();
+        sorted.sort_unstable_by(|a, b| a.partial_cmp(b).expect("got NaN value"));
+
+        let last = (sorted.len() - 1) as f64;
+        return quantiles.iter().map(|&quantile| {
+            assert!((0.0..=1.0).contains(&quantile), "quantile {} is not between 0 and 1", quantile);
+            let position = quantile * last;
+            let (below, above) = (position.floor() as usize, position.ceil() as usize);
+            let weight = T::from(position - below as f64).expect("failed to convert weight");
+            sorted[below] + (sorted[above] - sorted[below]) * weight
+        }).collect();
+    }
+
+    /// Get the squared radius of the whole dataset around the initial point,
+    /// i.e. the squared distance between the initial point and the point
+    /// farthest from it. This is the radius that triggers the selection of
+    /// the second point.
+    pub fn initial_radius2(&self) -> T {
+        self.initial_radius2
+    }
+
+    /// Get the quality of the current selection as a solution of the metric
+    /// k-center problem, with k the number of selected points.
+    ///
+    /// This returns `(achieved, lower_bound)`, where `achieved` is the
+    /// largest distance (not squared) between any point and its closest
+    /// selected point, and `lower_bound = achieved / 2` is a lower bound on
+    /// the optimal k-center radius. This comes from greedy FPS being a
+    /// 2-approximation of the k-center problem: the selected points together
+    /// with the next candidate are k + 1 points at least `achieved` apart, so
+    /// any k centers leave two of them in the same cluster.
+    ///
+    /// The bound only holds if all the points were selected by FPS, and not
+    /// manually added with `add_point`.
+    pub fn kcenter_bound(&self) -> (T, T) {
+        let (_, max_radius2) = find_max(self.cells.radius2.iter());
+        let achieved = max_radius2.sqrt();
+        let two = T::one() + T::one();
+        return (achieved, achieved / two);
+    }
+
+    /// Get the squared distance between the points at indexes `i` and `j`,
+    /// using the same distance as the decomposition (custom metric, periodic
+    /// boundary conditions, kernel, …).
+    ///
+    /// # Panics
+    ///
+    /// If `i` or `j` is out of bounds.
+    pub fn distance2(&self, i: usize, j: usize) -> T {
+        let n_points = self.distances.n_points();
+        assert!(i < n_points && j < n_points, "index out of range for {} points", n_points);
+        return self.distances.distance2(i, j);
+    }
+
+    /// Find the selected point closest to an arbitrary `query` vector (for
+    /// example a new point outside of the dataset), using the same distance
+    /// as the decomposition. This returns the position of this point in
+    /// `selected()`, and its squared distance to the query.
+    ///
+    /// This costs one distance computation per selected point. It returns an
+    /// error if `query` does not have the same number of features as the
+    /// points, if some distances are NaN, or for decomposers created from a
+    /// Gram matrix, which can not compute distances to new vectors.
+    pub fn nearest_center(&self, query: ArrayView1<'_, T>) -> Result<(usize, T), VoronoiError> {
+        let centers = &self.cells.center_idx;
+        let distances2 = self.distances.distances2_to_vector(centers, query)?;
+
+        let mut nearest = (0, T::infinity());
+        for (position, &d2) in distances2.iter().enumerate() {
+            if d2.is_nan() {
+                return Err(VoronoiError::NaNDistance { index: centers[position] });
+            }
+
+            if d2 < nearest.1 {
+                nearest = (position, d2);
+            }
+        }
+        return Ok(nearest);
+    }
+
+    /// Get the fill distance (squared) of the selection over a set of
+    /// `query_points`, for example a regular grid covering the space: the
+    /// largest squared distance between a query point and its closest selected
+    /// point, see `nearest_center`. This is zero if there are no query points.
+    ///
+    /// This costs one distance computation per selected point for each query
+    /// point, and returns the same errors as `nearest_center`.
+    pub fn fill_distance2(&self, query_points: ArrayView2<'_, T>) -> Result<T, VoronoiError> {
+        let nearest = into_par_iter!(query_points.axis_iter(Axis(0)))
+            .map(|query| self.nearest_center(query).map(|(_, d2)| d2))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        return Ok(nearest.into_iter().fold(T::zero(), T::max));
+    }
+
+    /// Get the centroid of each Voronoï cell, i.e. the mean of all the points
+    /// assigned to the cell, with one row per selected point in selection
+    /// order.
+    ///
+    /// This returns `None` for decomposers without the coordinates of the
+    /// points (created from a Gram matrix or from a sparse matrix). For
+    /// cosine distances, this uses the normalized points.
+    pub fn cell_centroids(&self) -> Option<Array2<T>> {
+        let points = self.distances.points()?;
+
+        let n_cells = self.cells.center_idx.len();
+        let mut centroids = Array2::zeros((n_cells, points.ncols()));
+        let mut counts = vec![0; n_cells];
+        for (point, &cell_idx) in points.rows().into_iter().zip(&self.assignments) {
+            centroids.row_mut(cell_idx).zip_mut_with(&point, |c, &p| *c = *c + p);
+            counts[cell_idx] += 1;
+        }
+
+        for (mut centroid, count) in centroids.rows_mut().into_iter().zip(counts) {
+            let count = T::from(count).expect("failed to convert count");
+            centroid.mapv_inplace(|v| v / count);
+        }
+
+        return Some(centroids);
+    }
+
+    /// Get the number of points for which the distance to the last selected
+    /// point had to be computed when adding it. Brute-force FPS would compute
+    /// the distance to all points: values close to the total number of points
+    /// mean that the Voronoï decomposition is not saving much work on this
+    /// dataset.
+    ///
+    /// For a freshly created decomposer, this is the number of points.
+    pub fn last_recompute_count(&self) -> usize {
+        self.last_recompute_count
+    }
+
+    /// Get the smallest squared distance between any two selected points,
+    /// measuring the diversity of the selection. This is tracked when adding
+    /// points, and is infinite if less than two points are selected.
+    pub fn selection_min_pairwise_distance2(&self) -> T {
+        self.min_center_distance2
+    }
+
+    /// Get the Voronoï partition of the points: for each point, this gives
+    /// the index (in `selected()`) of the selected point closest to it.
+    pub fn assignments(&self) -> &[usize] {
+        &self.assignments[..self.assignments.len() - self.n_reference]
+    }
+
+    /// Get the squared distance between each point and the closest selected
+    /// point, i.e. the center of its cell in `assignments()`.
+    ///
+    /// These distances are stored in the decomposer, so this does not need
+    /// to compute or allocate anything.
+    pub fn nearest_distances2(&self) -> Cow<'_, [T]> {
+        Cow::Borrowed(&self.haussdorf[..self.haussdorf.len() - self.n_reference])
+    }
+
+    /// Get the indexes of all the points selected so far (the initial point
+    /// and all points passed to `add_point`), in selection order
+    pub fn selected(&self) -> &[usize] {
+        &self.cells.center_idx[self.n_reference..]
+    }
+
+    /// Get a boolean mask over all points, which is `true` for the selected
+    /// points and `false` for all the others
+    pub fn selected_mask(&self) -> Array1<bool> {
+        let mut mask = Array1::from_elem(self.distances.n_points() - self.n_reference, false);
+        for &center in self.selected() {
+            mask[center] = true;
+        }
+        return mask;
+    }
+
+    /// Get the number of selected points
+    pub fn len(&self) -> usize {
+        self.cells.center_idx.len() - self.n_reference
+    }
+
+    /// Check if there are no selected points. This is always `false`, since
+    /// the initial point is selected when creating the decomposer, except for
+    /// decomposers created by `new_with_reference` without initial point.
+    pub fn is_empty(&self) -> bool {
+        self.cells.center_idx.len() == self.n_reference
+    }
+
+    /// Get the potential next point, i.e. the point with highest Haussdorf distance
+    ///
+    /// If multiple points are at the same maximal distance, this returns the
+    /// one with the lowest index, making the selection identical to
+    /// [`crate::fps_bruteforce`] even with ties.
+    ///
+    /// For weighted decomposers, this is the farthest point of the cell with
+    /// the highest `weight[farthest] * radius2` score, and the returned value
+    /// is still the squared radius of this cell.
+    pub fn next_point(&self) -> (usize, T) {
+        let (cell_idx, _) = self.best_cell();
+        return self.candidate(cell_idx);
+    }
+
+    /// Get the point that would be selected next by Farthest Points
+    /// Sampling, i.e. the farthest point of the cell with the largest radius,
+    /// together with the squared radius of this cell. This returns `None` if
+    /// all points are already selected (or if all the remaining points have a
+    /// zero weight, or are closer than the minimal separation set with
+    /// `set_min_separation2`).
+    ///
+    /// With a cutoff (see `set_cutoff`), this also returns `None` once the
+    /// squared radius of all cells is below the squared cutoff.
+    pub fn next_candidate(&self) -> Option<(usize, T)> {
+        let (cell_idx, score) = self.best_cell();
+        let (farthest, radius2) = self.candidate(cell_idx);
+        let within_cutoff = match self.cutoff {
+            Some(ref cutoff) => radius2 <= cutoff.cutoff2,
+            None => false,
+        };
+
+        if score > T::zero() && radius2 > T::zero() && !within_cutoff {
+            return Some((farthest, radius2));
+        } else {
+            return None;
+        }
+    }
+
+    /// Get the next point to select, like `next_candidate`, after excluding
+    /// all the candidates which are outliers for the current cutoff
+    fn next_selection(&mut self) -> Option<(usize, T)> {
+        loop {
+            let (point, radius2) = self.next_candidate()?;
+            let cutoff2 = match self.cutoff {
+                Some(ref cutoff) => cutoff.cutoff2,
+                None => return Some((point, radius2)),
+            };
+
+            if !self.is_isolated(point, cutoff2) {
+                return Some((point, radius2));
+            }
+
+            let n_points = self.haussdorf.len();
+            let cells = &self.cells;
+            let excluded = self.excluded.get_or_insert_with(|| Excluded {
+                mask: vec![false; n_points],
+                farthest: cells.farthest.clone(),
+                radius2: cells.radius2.clone(),
+            });
+            excluded.mask[point] = true;
+            self.update_candidate(self.assignments[point]);
+            self.cutoff.as_mut().expect("missing cutoff").outliers.push(point);
+        }
+    }
+
+    /// Check if all the points other than `point` are farther than
+    /// `sqrt(cutoff2)` from it. The cells and points which are too far from
+    /// `point` are skipped using the triangle inequality with the distance
+    /// between `point` and the cell center.
+    fn is_isolated(&self, point: usize, cutoff2: T) -> bool {
+        let cutoff = cutoff2.sqrt();
+        let distances = &self.distances;
+        let haussdorf = &self.haussdorf;
+        let has_neighbor = par_iter!(self.cells.points)
+            .zip(&self.cells.center_idx)
+            .zip(&self.cells.radius2)
+            .any(|((points, &center), &radius2)| {
+                let center_distance = distances.distance2(center, point).sqrt();
+                if center_distance - radius2.sqrt() > cutoff {
+                    return false;
+                }
+
+                return points.iter().any(|&other| {
+                    other != point
+                        && center_distance - haussdorf[other].sqrt() <= cutoff
+                        && distances.distance2(point, other) <= cutoff2
+                });
+            });
+
+        return !has_neighbor;
+    }
+
+    /// Check if the selection is complete, i.e. if no cell contains a point
+    /// which can still be selected. This happens once all points are either
+    /// selected or duplicates of selected points (or have a zero weight, are
+    /// excluded or are closer than the minimal separation), or once all cells
+    /// are within the cutoff set with `set_cutoff`. `select` and
+    /// `iter_selections` stop at this point instead of selecting points with a
+    /// zero radius.
+    pub fn is_exhausted(&self) -> bool {
+        return self.next_candidate().is_none();
+    }
+
+    /// Get the center and squared radius of the smallest cell, i.e. the most
+    /// redundant selected point. Cells containing only their center have a
+    /// radius of zero. If multiple cells have the same radius, this returns
+    /// the one selected first.
+    pub fn smallest_cell(&self) -> (usize, T) {
+        let mut smallest = 0;
+        for (cell_idx, &radius2) in self.cells.radius2.iter().enumerate().skip(1) {
+            if radius2 < self.cells.radius2[smallest] {
+                smallest = cell_idx;
+            }
+        }
+        return (self.cells.center_idx[smallest], self.cells.radius2[smallest]);
+    }
+
+    /// Get the point which can be selected next in the cell at `cell_idx`,
+    /// i.e. the farthest point which is not excluded, and its squared
+    /// distance to the center
+    fn candidate(&self, cell_idx: usize) -> (usize, T) {
+        match self.excluded {
+            Some(ref excluded) => (excluded.farthest[cell_idx], excluded.radius2[cell_idx]),
+            None => (self.cells.farthest[cell_idx], self.cells.radius2[cell_idx]),
+        }
+    }
+
+    /// Find the cell with the highest score, and return its index together
+    /// with the score. The score is the radius of the cell (ignoring excluded
+    /// points), multiplied by the weight of its farthest point for weighted
+    /// decomposers. Cells with a radius below the minimal separation have a
+    /// score of zero.
+    ///
+    /// The cells are compared in parallel with a tree reduction, using
+    /// `best_candidate` to merge the partial results. This gives the same
+    /// cell regardless of the number of threads.
+    fn best_cell(&self) -> (usize, T) {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+
+        let candidates = into_par_iter!(0..self.cells.center_idx.len()).map(|cell_idx| {
+            let (farthest, radius2) = self.candidate(cell_idx);
+            let score = match self.weights {
+                _ if radius2 < self.min_separation2 => T::zero(),
+                Some(ref weights) => weights[farthest] * radius2,
+                None => radius2,
+            };
+            (cell_idx, score, farthest)
+        });
+
+        #[cfg(feature = "rayon")]
+        let candidates = candidates.with_min_len(PARALLEL_MIN_CELLS);
+        let best = reduce_with!(candidates, best_candidate);
+
+        let (cell_idx, score, _) = best.expect("there should be cells");
+
+        #[cfg(feature = "metrics")]
+        {
+            let mut timings = self.timings.lock().expect("poisoned mutex");
+            timings.candidate_scans += start.elapsed();
+            timings.n_candidate_scans += 1;
+        }
+
+        return (cell_idx, score);
+    }
+
+    /// Select up to `n` new points using Farthest Points Sampling, starting
+    /// from the current state of the decomposition, and return the indexes of
+    /// the selected points in selection order.
+    ///
+    /// The selection stops early (and fewer than `n` points are returned) once
+    /// all points are selected, i.e. when all cells have a radius of zero, or
+    /// once all points are within the cutoff set with `set_cutoff`.
+    ///
+    /// The decomposer is kept between calls, so a selection can be continued
+    /// after inspecting the first points, giving the same points as a single
+    /// larger selection:
+    ///
+    /// ```
+    /// # use farthest_sampler::VoronoiDecomposer;
+    /// let points = ndarray::Array2::from_shape_fn((100, 3), |(i, j)| ((i * (j + 3)) as f64).sin());
+    /// let mut voronoi = VoronoiDecomposer::new(points.view().into(), 0);
+    ///
+    /// let first = voronoi.select(10);
+    /// // ... look at the first points, then select more
+    /// let second = voronoi.select(10);
+    ///
+    /// assert_eq!(voronoi.selected().len(), 21);
+    /// assert_eq!(voronoi.selected()[1..], [first, second].concat());
+    /// ```
+    pub fn select(&mut self, n: usize) -> Vec<usize> {
+        return self.select_with_radii(n).into_iter().map(|(point, _)| point).collect();
+    }
+
+    /// Select new points using Farthest Points Sampling until the largest
+    /// squared radius of the cells falls below `min_radius2`, and return the
+    /// indexes of the points selected by this call in selection order.
+    ///
+    /// The selection also stops once all points are selected, or if it can
+    /// not make any progress (for example with duplicated points).
+    pub fn select_until(&mut self, min_radius2: T) -> Vec<usize> {
+        let mut selected = Vec::new();
+        while let Some((new_point, radius2)) = self.next_selection() {
+            if radius2 < min_radius2 {
+                break;
+            }
+
+            let n_cells = self.len();
+            self.add_point(new_point);
+            if self.len() == n_cells {
+                // the point was already selected, the selection is stuck
+                break;
+            }
+
+            selected.push(new_point);
+        }
+
+        return selected;
+    }
+
+    /// Select new points using Farthest Points Sampling until the relative
+    /// decrease of the largest squared radius from one selection to the next,
+    /// `(previous_radius2 - new_radius2) / previous_radius2`, falls below
+    /// `relative_gain`. This returns the indexes of the points selected by
+    /// this call in selection order, including the last point which did not
+    /// decrease the radius enough.
+    ///
+    /// The largest radius can stay the same for multiple selections if
+    /// multiple cells have the same radius, stopping the selection early.
+    pub fn select_until_gain(&mut self, relative_gain: T) -> Vec<usize> {
+        let mut selected = Vec::new();
+        while let Some((new_point, previous_radius2)) = self.next_selection() {
+            let n_cells = self.len();
+            self.add_point(new_point);
+            if self.len() == n_cells {
+                // the point was already selected, the selection is stuck
+                break;
+            }
+            selected.push(new_point);
+
+            let (_, new_radius2) = self.next_point();
+            if (previous_radius2 - new_radius2) / previous_radius2 < relative_gain {
+                break;
+            }
+        }
+
+        return selected;
+    }
+
+    /// Get an iterator selecting new points lazily with Farthest Points
+    /// Sampling. Each call to `next()` adds a single point to the
+    /// decomposition, and yields its index together with the squared radius
+    /// that triggered its selection (see `select_with_radii`).
+    ///
+    /// The iterator ends once all points are selected.
+    pub fn iter_selections(&mut self) -> Selections<'_, 'a, T> {
+        Selections {
+            voronoi: self,
+        }
+    }
+
+    /// Same as `select`, but also return the squared Voronoï radius that
+    /// triggered the selection of each point, i.e. the largest `radius2`
+    /// across all cells just before the point was added.
+    ///
+    /// When starting from a freshly created decomposer, the first radius is
+    /// the radius of the whole dataset around the initial point.
+    pub fn select_with_radii(&mut self, n: usize) -> Vec<(usize, T)> {
+        self.reserve(n);
+
+        let mut selected = Vec::with_capacity(n);
+        for _ in 0..n {
+            // The maxmin point must be one of the farthest points from the
+            // Voronoï decomposition, so we only have to look at the list of
+            // existing cells to find it.
+            let (new_point, radius2) = match self.next_selection() {
+                Some(candidate) => candidate,
+                None => break,
+            };
+
+            let n_cells = self.len();
+            self.add_point(new_point);
+            if self.len() == n_cells {
+                // the point was already selected, the selection is stuck
+                break;
+            }
+
+            selected.push((new_point, radius2));
+        }
+
+        return selected;
+    }
+
+    /// Select up to `n` new points, calling `callback(index, radius2)` after
+    /// each point is added, with the same radius as `select_with_radii`. The
+    /// selection stops early if the callback returns `ControlFlow::Break`.
+    ///
+    /// This returns the indexes of the points selected by this call,
+    /// including the point for which the callback returned `Break`.
+    pub fn select_with<F>(&mut self, n: usize, mut callback: F) -> Vec<usize> where F: FnMut(usize, T) -> ControlFlow<()> {
+        let mut selected = Vec::new();
+        for (new_point, radius2) in self.iter_selections().take(n) {
+            selected.push(new_point);
+            if callback(new_point, radius2).is_break() {
+                break;
+            }
+        }
+
+        return selected;
+    }
+}
+
+/// Minimal number of cells handled by each thread when searching for the
+/// cell with the largest radius, below which the search runs serially
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_CELLS: usize = 256;
+
+/// Merge two `(cell_idx, score, farthest)` candidates for the next selected
+/// point, keeping the one with the highest score. If both have the same
+/// score, the one with the lowest farthest point index wins.
+///
+/// Different cells always have different farthest points, so this defines a
+/// total order on the candidates: reducing them in any order and with any
+/// partitioning gives the same result.
+fn best_candidate<T: Float>(a: (usize, T, usize), b: (usize, T, usize)) -> (usize, T, usize) {
+    if b.1 > a.1 || (b.1 == a.1 && b.2 < a.2) {
+        return b;
+    } else {
+        return a;
+    }
+}
+
+/// Get the error corresponding to the non-finite squared distance `d2`
+/// between the points at indexes `i` and `j`
+fn non_finite_error<T: Float>(i: usize, j: usize, d2: T) -> VoronoiError {
+    if d2.is_nan() {
+        return VoronoiError::NaNDistance { index: j };
+    } else {
+        return VoronoiError::NonFiniteDistance { i: i, j: j };
+    }
+}
+
+/// Create a decomposer with the Euclidean distance, starting the selection
+/// from the first point (see [`VoronoiDecomposer::from_points`])
+impl<'a, T: Float> TryFrom<ArrayView2<'a, T>> for VoronoiDecomposer<'a, T> {
+    type Error = VoronoiError;
+
+    fn try_from(points: ArrayView2<'a, T>) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        return VoronoiDecomposer::try_new(points.into(), 0);
+    }
+}
+
+/// Iterator over new points selected by Farthest Points Sampling, created by
+/// [`VoronoiDecomposer::iter_selections`].
+pub struct Selections<'v, 'a, T: Float> {
+    voronoi: &'v mut VoronoiDecomposer<'a, T>,
+}
+
+impl<'v, 'a, T: Float> Iterator for Selections<'v, 'a, T> {
+    type Item = (usize, T);
+
+    fn next(&mut self) -> Option<(usize, T)> {
+        let (new_point, radius2) = self.voronoi.next_selection()?;
+        let n_cells = self.voronoi.len();
+        self.voronoi.add_point(new_point);
+        if self.voronoi.len() == n_cells {
+            // the point was already selected, the selection is stuck
+            return None;
+        }
+
+        return Some((new_point, radius2));
+    }
+}
+
+/// Kind of decomposer recorded in a `VoronoiState`. Only decomposers created
+/// with `VoronoiDecomposer::new` can be saved and restored.
+#[cfg(feature = "serde")]
+const EUCLIDEAN_STATE: &str = "euclidean";
+
+/// Saved state of a `VoronoiDecomposer`, containing the cells and the
+/// distance of all points to their cell center, but not the points
+/// themselves. This can be used to checkpoint a selection and restore it
+/// later with [`VoronoiDecomposer::from_state`].
+///
+/// The decomposer itself can not be deserialized, since it needs the points
+/// to compute new distances. Deserialize this state instead, and restore the
+/// decomposer with the same points using `from_state`.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct VoronoiState<T> {
+    kind: String,
+    cells: VoronoiCells<T>,
+    haussdorf: Vec<T>,
+}
+
+/// Serialization of the state of the decomposer, to be deserialized as a
+/// `VoronoiState`. This fails for decomposers which can not be restored by
+/// `from_state`, i.e. anything else than a decomposer created with
+/// `VoronoiDecomposer::new` (possibly extended with new points).
+#[cfg(feature = "serde")]
+impl<'a, T: Float + serde::Serialize> serde::Serialize for VoronoiDecomposer<'a, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // this must be kept in sync with `VoronoiState`
+        #[derive(serde::Serialize)]
+        struct VoronoiStateRef<'b, T> {
+            kind: &'b str,
+            cells: &'b VoronoiCells<T>,
+            haussdorf: &'b [T],
+        }
+
+        let plain = self.weights.is_none() && self.excluded.is_none() && self.cutoff.is_none()
+            && self.min_separation2 == T::zero() && self.n_reference == 0;
+        if !self.distances.is_euclidean() || !plain {
+            return Err(serde::ser::Error::custom(
+                "only decomposers created with VoronoiDecomposer::new can be saved"
+            ));
+        }
+
+        let state = VoronoiStateRef {
+            kind: EUCLIDEAN_STATE,
+            cells: &self.cells,
+            haussdorf: &self.haussdorf,
+        };
+        return state.serialize(serializer);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'a, T: Float> VoronoiDecomposer<'a, T> {
+    /// Restore a decomposer created with `VoronoiDecomposer::new` from a
+    /// saved `state`. `points` must be the same points used to create the
+    /// saved decomposer.
+    ///
+    /// This returns an error if the state does not have one distance for
+    /// each point, if it was not created by a plain Euclidean decomposer, or
+    /// if each point is not in exactly one cell.
+    pub fn from_state(points: CowArray<'a, T, Ix2>, state: VoronoiState<T>) -> Result<VoronoiDecomposer<'a, T>, VoronoiError> {
+        let invalid = |message: &str| VoronoiError::InvalidState { message: message.into() };
+
+        if state.kind != EUCLIDEAN_STATE {
+            return Err(VoronoiError::InvalidState {
+                message: format!("unsupported decomposer kind '{}'", state.kind)
+            });
+        }
+
+        if state.haussdorf.len() != points.nrows() {
+            return Err(VoronoiError::StateMismatch { expected: state.haussdorf.len(), got: points.nrows() });
+        }
+
+        let n_cells = state.cells.center_idx.len();
+        if state.cells.farthest.len() != n_cells || state.cells.radius2.len() != n_cells || state.cells.points.len() != n_cells {
+            return Err(invalid("the cells arrays have different lengths"));
+        }
+
+        let initial = *state.cells.center_idx.first().ok_or(VoronoiError::EmptySelection)?;
+
+        let mut assignments = vec![usize::MAX; points.nrows()];
+        for (cell_idx, cell_points) in state.cells.points.iter().enumerate() {
+            for &point in cell_points {
+                if point >= points.nrows() {
+                    return Err(VoronoiError::IndexOutOfBounds { index: point, n_points: points.nrows() });
+                }
+                if assignments[point] != usize::MAX {
+                    return Err(invalid("a point is in multiple cells"));
+                }
+                assignments[point] = cell_idx;
+            }
+        }
+
+        if assignments.contains(&usize::MAX) {
+            return Err(invalid("a point is not in any cell"));
+        }
+
+        for cell_idx in 0..n_cells {
+            let center = state.cells.center_idx[cell_idx];
+            let farthest = state.cells.farthest[cell_idx];
+            if center >= points.nrows() {
+                return Err(VoronoiError::IndexOutOfBounds { index: center, n_points: points.nrows() });
+            }
+            if assignments[center] != cell_idx || farthest >= points.nrows() || assignments[farthest] != cell_idx {
+                return Err(invalid("the center or farthest point of a cell is not in this cell"));
+            }
+        }
+
+        let mut distances = EuclideanDistances::new(points);
+        for &center in &state.cells.center_idx {
+            distances.push_center(center);
+        }
+        let initial_radius2 = (0..distances.n_points())
+            .map(|point| distances.distance2(initial, point))
+            .fold(T::zero(), T::max);
+
+        let mut work = WorkArrays::new();
+        work.reserve(state.cells.center_idx.len());
+
+        let mut decomposer = VoronoiDecomposer {
+            distances: Box::new(distances),
+            cells: state.cells,
+            haussdorf: state.haussdorf,
+            assignments: assignments,
+            initial_radius2: initial_radius2,
+            min_center_distance2: T::infinity(),
+            last_recompute_count: 0,
+            weights: None,
+            excluded: None,
+            cutoff: None,
+            min_separation2: T::zero(),
+            n_reference: 0,
+            trace: None,
+            #[cfg(feature = "metrics")]
+            timings: Default::default(),
+            block_size: DEFAULT_BLOCK_SIZE,
+            work: work,
+        };
+        decomposer.min_center_distance2 = decomposer.compute_min_center_distance2();
+
+        Ok(decomposer)
+    }
+
+    /// Save the state of this decomposer to the file at `path`, using bincode
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> where T: serde::Serialize {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        bincode::serialize_into(file, self)?;
+        return Ok(());
+    }
+
+    /// Load a decomposer created with `VoronoiDecomposer::new` from the
+    /// state saved at `path` with `save`. `points` must be the same points
+    /// used to create the saved decomposer.
+    pub fn load<P: AsRef<std::path::Path>>(path: P, points: CowArray<'a, T, Ix2>) -> Result<VoronoiDecomposer<'a, T>, Box<dyn std::error::Error>> where T: serde::de::DeserializeOwned {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let state = bincode::deserialize_from(file)?;
+        return Ok(VoronoiDecomposer::from_state(points, state)?);
+    }
+}
+
+impl<'a> VoronoiDecomposer<'a, f64> {
+    /// Create a new decomposer for 3-dimensional `points` in a periodic
+    /// simulation cell, using the minimum image convention to compute
+    /// distances. The point at index `initial` is the first selected point.
+    ///
+    /// The rows of the `cell` matrix are the three cell vectors. This only
+    /// makes sense for Cartesian coordinates, and `points` must have exactly 3
+    /// columns. See [`Periodic`] for more information.
+    pub fn new_periodic(
+        points: CowArray<'a, f64, Ix2>,
+        initial: usize,
+        cell: [[f64; 3]; 3]
+    ) -> Result<VoronoiDecomposer<'a>, VoronoiError> {
+        if points.ncols() != 3 {
+            return Err(VoronoiError::InvalidDimension { expected: 3, got: points.ncols() });
+        }
+
+        let metric = Periodic::new(cell)?;
+        return VoronoiDecomposer::try_new_with_metric(points, initial, metric);
+    }
+}
+
+#[cfg(feature = "half")]
+impl<'a> VoronoiDecomposer<'a, f32> {
+    /// Create a new decomposer for `points` stored with 16-bit floating point
+    /// values (`half::f16` or `half::bf16`), using Euclidean distances. The
+    /// point at index `initial` is the first selected point.
+    ///
+    /// The points are kept in 16-bit storage, and only converted to `f32`
+    /// when computing distances, which are accumulated in `f32`. This
+    /// decomposer can not be extended with new points.
+    pub fn new_half<H>(points: CowArray<'a, H, Ix2>, initial: usize) -> Result<VoronoiDecomposer<'a, f32>, VoronoiError>
+        where H: Copy + Into<f32> + Send + Sync + std::fmt::Debug
+    {
+        let distances = crate::distances::HalfDistances::new(points);
+        return VoronoiDecomposer::try_from_distances(Box::new(distances), initial);
+    }
+}
+
+/// Select up to `n_select` points from `points` using Farthest Points Sampling,
+/// and return the indexes of selected points. The first point (already
+/// selected) is the point at the `initial` index.
+///
+/// Like `VoronoiDecomposer::select`, the selection stops early once all the
+/// remaining points are at a distance of zero from the selected ones (for
+/// example with duplicated points).
+#[cfg_attr(feature = "time-graph", time_graph::instrument)]
+pub fn select_fps<T: Float>(points: ArrayView2<'_, T>, n_select: usize, initial: usize) -> Vec<usize> {
+    let n_points = points.nrows();
+
+    if n_select > n_points {
+        panic!("can not select more points than what we have")
+    }
+
+    let mut voronoi = VoronoiDecomposer::new(points.into(), initial);
+    if n_select == 0 {
+        return Vec::new();
+    }
+    voronoi.select(n_select - 1);
+
+    return voronoi.selected().to_vec();
+}
+
+/// Collapse identical rows in `points`, keeping only the first occurrence of
+/// each. This returns the unique points, and for each unique point the index
+/// of the corresponding row in the original `points`.
+///
+/// Running FPS on the unique points avoids spending selections on exact
+/// duplicates, and the second array maps the selected indexes back to the
+/// original dataset.
+pub fn deduplicate<T: Float>(points: ArrayView2<'_, T>) -> (Array2<T>, Vec<usize>) {
+    let compare_rows = |&a: &usize, &b: &usize| {
+        for (x, y) in points.row(a).iter().zip(points.row(b)) {
+            match x.partial_cmp(y) {
+                Some(std::cmp::Ordering::Equal) => continue,
+                Some(ordering) => return ordering,
+                // NaN values are never equal to anything
+                None => return a.cmp(&b),
+            }
+        }
+        return std::cmp::Ordering::Equal;
+    };
+
+    let mut sorted = (0..points.nrows()).collect::<Vec<_>>();
+    sorted.sort_by(|a, b| compare_rows(a, b).then(a.cmp(b)));
+
+    let mut unique = Vec::new();
+    for (i, &index) in sorted.iter().enumerate() {
+        if i == 0 || compare_rows(&sorted[i - 1], &index) != std::cmp::Ordering::Equal {
+            unique.push(index);
+        }
+    }
+    unique.sort_unstable();
+
+    return (points.select(ndarray::Axis(0), &unique), unique);
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::metric::{Cosine, Euclidean, Manhattan};
+
+    use ndarray::{Array1, Array2};
+
+    use ndarray_npy::read_npy;
+    use std::path::PathBuf;
+
+    /// Load the Boston housing dataset used in the tests
+    pub(crate) fn boston() -> Array2<f64> {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         path.push("boston.npy");
-        let data: Array2<f64> = read_npy(path).unwrap();
+        return read_npy(path).unwrap();
+    }
+
+    /// Check that the squared distances in `actual` and `expected` are the
+    /// same. BLAS rounds dot products differently depending on the size of
+    /// the matrices, so these are only equal up to rounding errors when
+    /// different code paths computed the distances.
+    fn assert_same_distances2(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        if cfg!(feature = "blas") {
+            for (&actual, &expected) in actual.iter().zip(expected) {
+                assert!((actual - expected).abs() <= 1e-10 * expected.abs(), "{} != {}", actual, expected);
+            }
+        } else {
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn check_simple() {
+        let data = Array2::from_shape_vec((4, 2), vec![
+            0.0, 1.0,
+            0.8, 0.5,
+            0.0, 0.0,
+            1.0, 0.0,
+        ]).unwrap();
+
+        let expected = [0, 3, 2, 1];
+        for n_select in 1..expected.len() {
+            let selected = select_fps(data.view(), n_select, expected[0]);
+            assert_eq!(selected, expected[..n_select]);
+        }
+    }
+
+    #[test]
+    fn select_continues() {
+        let data = Array2::from_shape_vec((4, 2), vec![
+            0.0, 1.0,
+            0.8, 0.5,
+            0.0, 0.0,
+            1.0, 0.0,
+        ]).unwrap();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(voronoi.next_candidate(), Some((3, 2.0)));
+        assert_eq!(voronoi.select(2), [3, 2]);
+        assert_eq!(voronoi.select(1), [1]);
+        assert_eq!(voronoi.selected(), [0, 3, 2, 1]);
+
+        // all points are already selected
+        assert_eq!(voronoi.next_candidate(), None);
+        assert_eq!(voronoi.select(3), []);
+    }
+
+    #[test]
+    fn select_until() {
+        let data = Array2::from_shape_vec((6, 2), vec![
+            0.0, 1.0,
+            0.8, 0.5,
+            0.0, 0.0,
+            1.0, 0.0,
+            1.0, 0.0,
+            0.0, 0.0,
+        ]).unwrap();
+
+        // points 3/4 and 2/5 are duplicated, and only one of each is selected
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(voronoi.select_until(0.5).len(), 2);
+        assert_eq!(voronoi.select_until(0.0), [1]);
+        assert_eq!(voronoi.select_until(0.0), []);
+    }
+
+    #[test]
+    fn select_until_gain() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let selected = voronoi.select_until_gain(0.01);
+        assert!(!selected.is_empty());
+
+        // all the selections but the last one decreased the radius enough
+        let mut reference = VoronoiDecomposer::new(data.view().into(), 0);
+        let radii = reference.select_with_radii(selected.len() + 1);
+        assert_eq!(radii[..selected.len()].iter().map(|r| r.0).collect::<Vec<_>>(), selected);
+        for window in radii.windows(2).take(selected.len() - 1) {
+            assert!((window[0].1 - window[1].1) / window[0].1 >= 0.01);
+        }
+        let last = &radii[selected.len() - 1..];
+        assert!((last[0].1 - last[1].1) / last[0].1 < 0.01);
+
+        // a gain larger than one stops after a single selection
+        assert_eq!(voronoi.select_until_gain(2.0).len(), 1);
+    }
+
+    #[test]
+    fn select_with() {
+        let data = boston();
+
+        let mut reference = VoronoiDecomposer::new(data.view().into(), 0);
+        let expected = reference.select_with_radii(20);
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let mut calls = Vec::new();
+        let selected = voronoi.select_with(20, |point, radius2| {
+            calls.push((point, radius2));
+            ControlFlow::Continue(())
+        });
+        assert_eq!(calls, expected);
+        assert_eq!(selected, expected.iter().map(|e| e.0).collect::<Vec<_>>());
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let mut count = 0;
+        let selected = voronoi.select_with(20, |_, _| {
+            count += 1;
+            if count == 5 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        });
+        assert_eq!(selected.len(), 5);
+        assert_eq!(voronoi.len(), 6);
+    }
+
+    #[test]
+    fn assignments() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(40);
+
+        let assignments = voronoi.assignments();
+        assert_eq!(assignments.len(), 506);
+        for (cell_idx, cell_points) in voronoi.cells().points.iter().enumerate() {
+            for &point in cell_points {
+                assert_eq!(assignments[point], cell_idx);
+            }
+        }
+
+        let nearest_distances2 = voronoi.nearest_distances2();
+        assert_eq!(nearest_distances2.len(), 506);
+        for (point, &cell_idx) in assignments.iter().enumerate() {
+            let center = data.row(voronoi.selected()[cell_idx]);
+            let distance2 = Euclidean.distance2(data.row(point), center);
+            assert!((nearest_distances2[point] - distance2).abs() < 1e-9 * distance2.max(1.0));
+            for &other in voronoi.selected() {
+                assert!(distance2 <= Euclidean.distance2(data.row(point), data.row(other)) + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn coverage() {
+        let data: Array2<f64> = Array2::from_shape_vec((4, 2), vec![
+            0.0, 1.0,
+            0.8, 0.5,
+            0.0, 0.0,
+            1.0, 0.0,
+        ]).unwrap();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(1);
+
+        let coverage = voronoi.coverage();
+        assert_eq!(coverage.max_radius2, 1.0);
+        assert!((coverage.mean_distance2 - (1.0 + 0.29) / 4.0).abs() < 1e-12);
+        assert_eq!(coverage.cell_sizes.into_iter().collect::<Vec<_>>(), [(2, 2)]);
+
+        // the squared distances are [0, 0, 0.29, 1]
+        assert!((coverage.quantiles_distance2[0] - 0.145).abs() < 1e-12);
+        assert!((coverage.quantiles_distance2[1] - 0.787).abs() < 1e-12);
+        assert!((coverage.quantiles_distance2[2] - 0.9787).abs() < 1e-12);
+
+        let quantiles = voronoi.distance2_quantiles(&[0.0, 0.25, 1.0]);
+        assert_eq!(quantiles[0], 0.0);
+        assert_eq!(quantiles[1], 0.0);
+        assert_eq!(quantiles[2], 1.0);
+    }
+
+    #[test]
+    fn select_with_radii() {
+        let data = Array2::from_shape_vec((4, 2), vec![
+            0.0, 1.0,
+            0.8, 0.5,
+            0.0, 0.0,
+            1.0, 0.0,
+        ]).unwrap();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        assert!(f64::abs(voronoi.initial_radius2() - 2.0) < 1e-12);
+        let selected = voronoi.select_with_radii(3);
+        assert_eq!(selected[0].1, voronoi.initial_radius2());
+
+        let points = selected.iter().map(|&(point, _)| point).collect::<Vec<_>>();
+        assert_eq!(points, [3, 2, 1]);
+
+        let radii = selected.iter().map(|&(_, radius2)| radius2).collect::<Vec<_>>();
+        let expected: [f64; 3] = [2.0, 1.0, 0.29];
+        for (radius2, expected) in radii.iter().zip(&expected) {
+            assert!((radius2 - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn duplicates() {
+        let data = boston();
+
+        // all points are duplicated, and the first 100 points are present three times
+        let data = ndarray::concatenate![ndarray::Axis(0), data, data.slice(ndarray::s![..100, ..]), data];
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let selected = voronoi.select_with_radii(data.nrows());
+
+        assert_eq!(selected.len(), 505);
+        for window in selected.windows(2) {
+            assert!(window[1].1 <= window[0].1);
+        }
+        assert!(selected.iter().all(|&(_, radius2)| radius2 > 0.0));
+
+        let (unique, original) = deduplicate(data.view());
+        assert_eq!(unique.nrows(), 506);
+        assert_eq!(original, (0..506).collect::<Vec<_>>());
+
+        // the duplicated selection can pick any copy of the points
+        let canonical = |i: usize| if i < 506 { i } else if i < 606 { i - 506 } else { i - 606 };
+        let selected = select_fps(data.view(), 10, 0).into_iter().map(canonical).collect::<Vec<_>>();
+        let unique_selected = select_fps(unique.view(), 10, 0).into_iter().map(|i| original[i]).collect::<Vec<_>>();
+        assert_eq!(unique_selected, selected);
+    }
+
+    #[test]
+    fn select_fps_duplicates() {
+        let data = Array2::from_shape_vec((4, 2), vec![
+            0.0, 0.0,
+            0.0, 0.0,
+            1.0, 1.0,
+            1.0, 1.0,
+        ]).unwrap();
+
+        // the selection stops once only duplicates of the selected points remain
+        assert_eq!(select_fps(data.view(), 4, 0), [0, 2]);
+        assert_eq!(select_fps(data.view(), 4, 1), [1, 2]);
+        assert_eq!(select_fps(data.view(), 1, 0), [0]);
+        assert_eq!(select_fps(data.view(), 0, 0), []);
+    }
+
+    #[test]
+    fn errors() {
+        let empty = Array2::<f64>::zeros((0, 3));
+        let error = VoronoiDecomposer::try_new(empty.view().into(), 0).unwrap_err();
+        assert_eq!(error, VoronoiError::EmptyDataset);
+
+        let mut data: Array2<f64> = Array2::zeros((5, 3));
+        data[[1, 0]] = 1.0;
+        data[[2, 0]] = 2.0;
+
+        let error = VoronoiDecomposer::try_new(data.view().into(), 5).unwrap_err();
+        assert_eq!(error, VoronoiError::IndexOutOfBounds { index: 5, n_points: 5 });
+
+        let mut voronoi = VoronoiDecomposer::try_new(data.view().into(), 0).unwrap();
+        assert_eq!(voronoi.try_add_point(2), Ok(()));
+        let error = voronoi.try_add_point(12).unwrap_err();
+        assert_eq!(error, VoronoiError::IndexOutOfBounds { index: 12, n_points: 5 });
+        assert_eq!(voronoi.selected(), [0, 2]);
+
+        let mut data = data.clone();
+        data[[3, 1]] = f64::NAN;
+        let error = VoronoiDecomposer::try_new(data.view().into(), 0).unwrap_err();
+        assert_eq!(error, VoronoiError::NaNDistance { index: 3 });
+
+        // the squared norms fit in f32, but not the squared distance
+        let huge = ndarray::arr2(&[[0.0f32, 0.0], [1.2e19, 0.0], [-1.2e19, 0.0]]);
+        let error = VoronoiDecomposer::try_new(huge.view().into(), 1).unwrap_err();
+        assert_eq!(error, VoronoiError::NonFiniteDistance { i: 1, j: 2 });
+
+        // the same overflow when adding a point leaves the decomposer unchanged
+        let mut voronoi = VoronoiDecomposer::try_new(huge.view().into(), 0).unwrap();
+        let error = voronoi.try_add_point(1).unwrap_err();
+        assert_eq!(error, VoronoiError::NonFiniteDistance { i: 1, j: 2 });
+        assert_eq!(voronoi.selected(), [0]);
+        assert_eq!(voronoi.assignments(), [0, 0, 0]);
+        let expected = VoronoiDecomposer::try_new(huge.view().into(), 0).unwrap();
+        assert_eq!(voronoi.cells().radius2, expected.cells().radius2);
+        assert_eq!(voronoi.cells().farthest, expected.cells().farthest);
+
+        // the sum of squared norms overflows, giving NaN
+        let huge = ndarray::arr2(&[[0.0f32, 0.0], [1.5e19, 0.0], [-1.5e19, 0.0]]);
+        let error = VoronoiDecomposer::try_new(huge.view().into(), 1).unwrap_err();
+        assert_eq!(error, VoronoiError::NaNDistance { index: 1 });
+    }
+
+    #[test]
+    fn with_capacity() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::with_capacity(data.view().into(), 0, 100);
+        assert!(voronoi.cells.center_idx.capacity() >= 100);
+        assert!(voronoi.cells.radius2.capacity() >= 100);
+        assert!(voronoi.work.distance_to_new_point.capacity() >= 100);
+
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(voronoi.select(50), expected.select(50));
+    }
+
+    #[test]
+    fn block_size() {
+        let data = boston();
+
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 0);
+        let (expected_points, expected_radii): (Vec<_>, Vec<_>) = expected.select_with_radii(100).into_iter().unzip();
+
+        for &block_size in &[1, 7, 256, 1000] {
+            let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+            voronoi.set_block_size(block_size);
+            let (points, radii): (Vec<_>, Vec<_>) = voronoi.select_with_radii(100).into_iter().unzip();
+            assert_eq!(points, expected_points);
+            assert_same_distances2(&radii, &expected_radii);
+        }
+    }
+
+    #[test]
+    fn remove_last() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let selected = voronoi.select(30);
+        for &point in selected[25..].iter().rev() {
+            assert_eq!(voronoi.remove_last(), Some(point));
+        }
+
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 0);
+        expected.select(25);
+
+        assert_eq!(voronoi.selected(), expected.selected());
+        assert_eq!(voronoi.assignments(), expected.assignments());
+        assert_same_distances2(&voronoi.haussdorf, &expected.haussdorf);
+        assert_same_distances2(voronoi.cells().radius2, expected.cells().radius2);
+        assert_eq!(voronoi.select(5), selected[25..]);
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 3);
+        assert_eq!(voronoi.remove_last(), None);
+        assert_eq!(voronoi.selected(), [3]);
+        assert_eq!(voronoi.len(), 1);
+        assert!(!voronoi.is_empty());
+    }
+
+    #[test]
+    fn repeated_select() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let mut selected = voronoi.select(100);
+        selected.extend(voronoi.select(100));
+
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(selected, expected.select(200));
+        assert_eq!(voronoi.cells().radius2, expected.cells().radius2);
+    }
+
+    #[test]
+    fn reference() {
+        let data = boston();
+        let pool = data.slice(ndarray::s![..400, ..]);
+        let reference = data.slice(ndarray::s![400.., ..]);
+
+        let mut voronoi = VoronoiDecomposer::new_with_reference(pool, reference, None).unwrap();
+        assert!(voronoi.selected().is_empty());
+        assert_eq!(voronoi.len(), 0);
+        assert_eq!(voronoi.remove_last(), None);
+        let selected = voronoi.select(20);
+        assert_eq!(voronoi.selected(), selected);
+        assert_eq!(voronoi.assignments().len(), 400);
+        assert_eq!(voronoi.nearest_distances2().len(), 400);
+        assert_eq!(voronoi.selected_mask().len(), 400);
+        assert_eq!(voronoi.selected_mask().iter().filter(|&&selected| selected).count(), 20);
+        let error = voronoi.try_add_point(400).unwrap_err();
+        assert_eq!(error, VoronoiError::IndexOutOfBounds { index: 400, n_points: 400 });
+        assert_eq!(voronoi.extend_dataset(pool).unwrap_err(), VoronoiError::CanNotExtend);
+
+        // brute force FPS, starting from the distances to the reference
+        let distance2 = |a: ArrayView1<f64>, b: ArrayView1<f64>| {
+            let diff = &a - &b;
+            diff.dot(&diff)
+        };
+        let mut min_distances = pool.rows().into_iter().map(|point| {
+            reference.rows().into_iter().map(|r| distance2(point, r)).fold(f64::INFINITY, f64::min)
+        }).collect::<Vec<_>>();
+        let mut expected = Vec::new();
+        for _ in 0..20 {
+            let (new, _) = find_max(min_distances.iter());
+            expected.push(new);
+            for (j, d2) in min_distances.iter_mut().enumerate() {
+                *d2 = d2.min(distance2(pool.row(new), pool.row(j)));
+            }
+        }
+        assert_eq!(selected, expected);
+
+        // with an initial point, selected after the reference points
+        let mut voronoi = VoronoiDecomposer::new_with_reference(pool, reference, Some(42)).unwrap();
+        assert_eq!(voronoi.selected(), [42]);
+        let selected = voronoi.select(5);
+        for (j, d2) in min_distances.iter_mut().enumerate() {
+            *d2 = reference.rows().into_iter().chain(std::iter::once(pool.row(42)))
+                .map(|r| distance2(pool.row(j), r))
+                .fold(f64::INFINITY, f64::min);
+        }
+        let mut expected = Vec::new();
+        for _ in 0..5 {
+            let (new, _) = find_max(min_distances.iter());
+            expected.push(new);
+            for (j, d2) in min_distances.iter_mut().enumerate() {
+                *d2 = d2.min(distance2(pool.row(new), pool.row(j)));
+            }
+        }
+        assert_eq!(selected, expected);
+        assert_eq!(voronoi.remove_last(), Some(expected[4]));
+        assert_eq!(voronoi.len(), 5);
+
+        // a small pool with a single reference point
+        let small = ndarray::arr2(&[[0.0], [1.0], [3.0]]);
+        let mut voronoi = VoronoiDecomposer::new_with_reference(small.view(), ndarray::arr2(&[[0.5]]).view(), None).unwrap();
+        assert_eq!(voronoi.select(3), [2, 0, 1]);
+        assert_eq!(voronoi.selected(), [2, 0, 1]);
+        assert_eq!(voronoi.len(), 3);
+        assert_eq!(voronoi.assignments().len(), 3);
+        assert_eq!(voronoi.selected_mask(), ndarray::arr1(&[true, true, true]));
+
+        let error = VoronoiDecomposer::new_with_reference(pool, reference.slice(ndarray::s![..0, ..]), None).err();
+        assert_eq!(error, Some(VoronoiError::EmptySelection));
+
+        let error = VoronoiDecomposer::new_with_reference(pool, reference.slice(ndarray::s![.., ..3]), None).err();
+        assert_eq!(error, Some(VoronoiError::InvalidDimension { expected: 13, got: 3 }));
+
+        let error = VoronoiDecomposer::new_with_reference(pool, reference, Some(400)).err();
+        assert_eq!(error, Some(VoronoiError::IndexOutOfBounds { index: 400, n_points: 400 }));
+    }
+
+    #[test]
+    fn weighted() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let unweighted = voronoi.select(100);
+        let outlier = unweighted[80];
+
+        let mut weights = Array1::from_elem(data.nrows(), 1.0);
+        weights[outlier] = 10.0;
+        let mut voronoi = VoronoiDecomposer::new_weighted(data.view().into(), weights.view(), 0).unwrap();
+        let weighted = voronoi.select(100);
+
+        let position = weighted.iter().position(|&point| point == outlier).unwrap();
+        assert!(position < 80, "the outlier was selected in position {}", position);
+
+        let ones = Array1::from_elem(data.nrows(), 1.0);
+        let mut voronoi = VoronoiDecomposer::new_weighted(data.view().into(), ones.view(), 0).unwrap();
+        assert_eq!(voronoi.select(100), unweighted);
+
+        let error = VoronoiDecomposer::new_weighted(data.view().into(), ones.slice(ndarray::s![..3]), 0).err();
+        assert_eq!(error, Some(VoronoiError::InvalidWeights { expected: 506, got: 3 }));
+
+        weights[12] = -1.0;
+        let error = VoronoiDecomposer::new_weighted(data.view().into(), weights.view(), 0).err();
+        assert_eq!(error, Some(VoronoiError::NegativeWeight { index: 12 }));
+    }
+
+    #[test]
+    fn min_separation2() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let radii = voronoi.select_with_radii(100);
+        let min_separation2 = radii[60].1;
+
+        // without weights, this is the same as select_until
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.set_min_separation2(min_separation2);
+        let selected = voronoi.select(100);
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(selected, expected.select_until(min_separation2));
+        assert!(voronoi.is_exhausted());
+        assert!(voronoi.selection_min_pairwise_distance2() >= min_separation2);
+
+        // with weights, small cells are skipped for the next best candidate
+        let mut weights = Array1::from_elem(data.nrows(), 1.0);
+        for &(point, _) in &radii[70..] {
+            weights[point] = 1e6;
+        }
+        let mut voronoi = VoronoiDecomposer::new_weighted(data.view().into(), weights.view(), 0).unwrap();
+        voronoi.set_min_separation2(min_separation2);
+        let selected = voronoi.select(100);
+        assert!(!selected.is_empty());
+        assert!(voronoi.selection_min_pairwise_distance2() >= min_separation2);
+
+        let mut unconstrained = VoronoiDecomposer::new_weighted(data.view().into(), weights.view(), 0).unwrap();
+        unconstrained.select(selected.len());
+        assert!(unconstrained.selection_min_pairwise_distance2() < min_separation2);
+    }
+
+    #[test]
+    #[cfg(feature = "metrics")]
+    fn timings() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(10);
+        voronoi.add_points(vec![3, 4, 5]);
+
+        let timings = voronoi.take_timings();
+        assert!(timings.construction > std::time::Duration::ZERO);
+        assert!(timings.add_points > std::time::Duration::ZERO);
+        assert_eq!(timings.n_add_points, 11);
+        assert_eq!(timings.n_candidate_scans, 10);
+
+        voronoi.next_candidate();
+        let timings = voronoi.take_timings();
+        assert_eq!(timings.construction, std::time::Duration::ZERO);
+        assert_eq!(timings.n_add_points, 0);
+        assert_eq!(timings.n_candidate_scans, 1);
+    }
+
+    #[test]
+    fn multi_view() {
+        let data = boston();
+
+        let first = data.slice(ndarray::s![.., ..5]);
+        let second = data.slice(ndarray::s![.., 5..]);
+        let mut voronoi = VoronoiDecomposer::new_multi_view(&[first, second], Some(&[2.0, 0.5]), 3).unwrap();
+        let selected = voronoi.select_with_radii(100);
+
+        // same as the concatenation of the scaled features
+        let mut scaled = data.clone();
+        scaled.slice_mut(ndarray::s![.., ..5]).mapv_inplace(|v| v * 2.0f64.sqrt());
+        scaled.slice_mut(ndarray::s![.., 5..]).mapv_inplace(|v| v * 0.5f64.sqrt());
+        let mut expected = VoronoiDecomposer::new(scaled.view().into(), 3);
+        let expected = expected.select_with_radii(100);
+        for (&(point, radius2), &(expected_point, expected_radius2)) in selected.iter().zip(&expected) {
+            assert_eq!(point, expected_point);
+            assert!((radius2 - expected_radius2).abs() < 1e-9 * expected_radius2);
+        }
+
+        let first_d2 = (&first.row(12) - &first.row(42)).mapv(|v| v * v).sum();
+        let second_d2 = (&second.row(12) - &second.row(42)).mapv(|v| v * v).sum();
+        let d2 = voronoi.distance2(12, 42);
+        assert!((d2 - (2.0 * first_d2 + 0.5 * second_d2)).abs() < 1e-9 * d2);
+
+        // queries and new points use the concatenated features
+        let (position, _) = voronoi.nearest_center(data.row(42)).unwrap();
+        assert_eq!(position, voronoi.assignments()[42]);
+        voronoi.extend_dataset(data.slice(ndarray::s![..10, ..])).unwrap();
+        assert_eq!(voronoi.assignments().len(), 516);
+        for point in 0..10 {
+            let nearest2 = voronoi.nearest_distances2();
+            assert!((nearest2[506 + point] - nearest2[point]).abs() <= 1e-9 * nearest2[point]);
+        }
+
+        let mut unweighted = VoronoiDecomposer::new_multi_view(&[first, second], None, 3).unwrap();
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 3);
+        assert_eq!(unweighted.select(50), expected.select(50));
+
+        let error = VoronoiDecomposer::<f64>::new_multi_view(&[], None, 0).err();
+        assert_eq!(error, Some(VoronoiError::EmptyDataset));
+
+        let error = VoronoiDecomposer::new_multi_view(&[first, second.slice(ndarray::s![..10, ..])], None, 0).err();
+        assert_eq!(error, Some(VoronoiError::InvalidView { view: 1, expected: 506, got: 10 }));
+
+        let error = VoronoiDecomposer::new_multi_view(&[first, second], Some(&[1.0]), 0).err();
+        assert_eq!(error, Some(VoronoiError::InvalidWeights { expected: 2, got: 1 }));
+
+        let error = VoronoiDecomposer::new_multi_view(&[first, second], Some(&[1.0, -1.0]), 0).err();
+        assert_eq!(error, Some(VoronoiError::NegativeWeight { index: 1 }));
+
+        let error = voronoi.nearest_center(first.row(0)).err();
+        assert_eq!(error, Some(VoronoiError::InvalidDimension { expected: 13, got: 5 }));
+    }
+
+    #[test]
+    fn excluded() {
+        let data = boston();
+
+        // exclude the first points selected without exclusion
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let excluded = voronoi.select(30).into_iter().step_by(2).collect::<Vec<_>>();
+
+        let mut voronoi = VoronoiDecomposer::new_with_excluded(data.view().into(), 0, &excluded).unwrap();
+        let selected = voronoi.select(100);
+
+        // brute force FPS, only picking points which are not excluded
+        let mut expected = Vec::new();
+        let distance2 = |i: usize, j: usize| {
+            let diff = &data.row(i) - &data.row(j);
+            diff.dot(&diff)
+        };
+        let mut min_distances = (0..data.nrows()).map(|j| distance2(0, j)).collect::<Vec<_>>();
+        for _ in 0..100 {
+            let mut new = 0;
+            for (point, &d2) in min_distances.iter().enumerate() {
+                if !excluded.contains(&point) && d2 > min_distances[new] {
+                    new = point;
+                }
+            }
+            expected.push(new);
+            for (j, d2) in min_distances.iter_mut().enumerate() {
+                *d2 = d2.min(distance2(new, j));
+            }
+        }
+        assert_eq!(selected, expected);
+
+        // excluded points are still part of the cells and their radius
+        let max_radius2 = voronoi.cells().radius2.iter().copied().fold(0.0, f64::max);
+        let max_distance2 = min_distances.iter().copied().fold(0.0, f64::max);
+        assert!((max_radius2 - max_distance2).abs() < 1e-9 * max_distance2);
+        assert!(voronoi.next_point().1 < max_radius2);
+
+        assert_eq!(voronoi.remove_last(), Some(expected[99]));
+        assert_eq!(voronoi.select(1), [expected[99]]);
+
+        let error = VoronoiDecomposer::new_with_excluded(data.view().into(), excluded[3], &excluded).err();
+        assert_eq!(error, Some(VoronoiError::ExcludedInitial { index: excluded[3] }));
+
+        let error = VoronoiDecomposer::new_with_excluded(data.view().into(), 0, &[1000]).err();
+        assert_eq!(error, Some(VoronoiError::IndexOutOfBounds { index: 1000, n_points: 506 }));
+    }
+
+    #[test]
+    fn cutoff() {
+        // two grids of points, a pair of close points, and isolated points
+        let mut rows = Vec::new();
+        for &offset in &[0.0, 10.0] {
+            for i in 0..10 {
+                for j in 0..10 {
+                    rows.push([offset + 0.1 * i as f64, offset + 0.1 * j as f64]);
+                }
+            }
+        }
+        rows.push([100.0, 100.0]);
+        rows.push([100.0, 100.3]);
+        let isolated = [rows.len(), rows.len() + 1, rows.len() + 2];
+        rows.push([50.0, 50.0]);
+        rows.push([-40.0, 0.0]);
+        rows.push([0.0, 80.0]);
+
+        let mut data = Array2::zeros((rows.len(), 2));
+        for (mut row, point) in data.rows_mut().into_iter().zip(&rows) {
+            row[0] = point[0];
+            row[1] = point[1];
+        }
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.set_cutoff(0.5);
+        let selected = voronoi.select(1000);
+        assert!(selected.len() < 50);
+        assert!(voronoi.is_exhausted());
+
+        let mut outliers = voronoi.outliers().to_vec();
+        outliers.sort_unstable();
+        assert_eq!(outliers, isolated);
+
+        let distance2 = |i: usize, j: usize| {
+            let diff = &data.row(i) - &data.row(j);
+            diff.dot(&diff)
+        };
+        for point in 0..data.nrows() {
+            let nearest2 = voronoi.selected().iter().map(|&s| distance2(s, point)).fold(f64::INFINITY, f64::min);
+            if isolated.contains(&point) {
+                assert!(nearest2 > 0.25);
+            } else {
+                assert!(nearest2 <= 0.25, "point {} is not within the cutoff", point);
+            }
+        }
+
+        // a new point close to an outlier makes it a regular point again
+        voronoi.extend_dataset(ndarray::arr2(&[[50.2, 50.0]]).view()).unwrap();
+        assert!(voronoi.outliers().is_empty());
+        voronoi.select(1000);
+        let mut outliers = voronoi.outliers().to_vec();
+        outliers.sort_unstable();
+        assert_eq!(outliers, isolated[1..]);
+        assert!(voronoi.selected().contains(&isolated[0]) || voronoi.selected().contains(&data.nrows()));
+    }
+
+    #[test]
+    fn trace() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(5);
+        assert!(voronoi.take_trace().is_empty());
+
+        voronoi.enable_trace();
+        let before = voronoi.assignments().to_vec();
+        let selected = voronoi.select_with_radii(20);
+        let trace = voronoi.take_trace();
+        assert_eq!(trace.len(), 20);
+        assert!(voronoi.take_trace().is_empty());
+
+        for (event, &(point, radius2)) in trace.iter().zip(&selected) {
+            assert_eq!(event.point, point);
+            assert_eq!(event.radius2, radius2);
+            assert!(event.n_reassigned >= 1);
+            assert!(event.n_distances <= data.nrows() + voronoi.len());
+        }
+
+        // the first traced event moved points from the cells they were in
+        let first = &trace[0];
+        let mut expected_sources = before.iter().zip(voronoi.assignments())
+            .filter(|&(_, &after)| after == 6)
+            .map(|(&before, _)| before)
+            .collect::<Vec<_>>();
+        expected_sources.sort_unstable();
+        expected_sources.dedup();
+        // later selections can steal points back from cell 6, so only check
+        // that the remaining points came from the recorded source cells
+        assert!(expected_sources.iter().all(|cell| first.source_cells.contains(cell)));
+    }
+
+    #[test]
+    fn kcenter_bound() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(9);
+        let (achieved, lower_bound) = voronoi.kcenter_bound();
+        assert_eq!(achieved, voronoi.coverage().max_radius2.sqrt());
+        assert_eq!(lower_bound, achieved / 2.0);
+
+        // any set of 10 centers must have a radius larger than the lower bound
+        let mut other = VoronoiDecomposer::new(data.view().into(), 100);
+        other.select(9);
+        assert!(other.kcenter_bound().0 >= lower_bound);
+
+        voronoi.select(data.nrows());
+        assert_eq!(voronoi.kcenter_bound(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn snapshot_cells() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(10);
+        let snapshot = voronoi.snapshot_cells();
+        voronoi.select(10);
+
+        assert_eq!(snapshot.center_idx, voronoi.selected()[..11]);
+        assert_eq!(snapshot.farthest.len(), 11);
+        assert_eq!(snapshot.radius2.len(), 11);
+        assert_eq!(voronoi.cells().len(), 21);
+    }
+
+    #[test]
+    fn duplicated_center() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let selected = voronoi.select(20);
+        let before = voronoi.snapshot_cells();
+        let assignments = voronoi.assignments().to_vec();
+
+        voronoi.add_point(selected[5]);
+        voronoi.add_point(0);
+        voronoi.add_points(vec![selected[3], selected[3]]);
+        assert_eq!(voronoi.snapshot_cells(), before);
+        assert_eq!(voronoi.assignments(), assignments);
+
+        let error = voronoi.try_add_point(selected[7]).unwrap_err();
+        assert_eq!(error, VoronoiError::AlreadySelected { index: selected[7] });
+        assert_eq!(voronoi.snapshot_cells(), before);
+
+        // the selection continues as if nothing happened
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 0);
+        expected.select(20);
+        assert_eq!(voronoi.select(10), expected.select(10));
+    }
+
+    #[test]
+    fn nearest_center() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(20);
+
+        // the points in the dataset are closest to their cell center
+        for point in [3, 42, 123, 505].iter().copied() {
+            let (position, d2) = voronoi.nearest_center(data.row(point)).unwrap();
+            assert_eq!(position, voronoi.assignments()[point]);
+            assert!((d2 - voronoi.distance2(point, voronoi.selected()[position])).abs() < 1e-6);
+        }
+
+        let query = data.row(7).mapv(|v| v * 1.5);
+        let (position, d2) = voronoi.nearest_center(query.view()).unwrap();
+        for &center in voronoi.selected() {
+            let diff = &query - &data.row(center);
+            assert!(diff.dot(&diff) >= d2 - 1e-6);
+        }
+        let diff = &query - &data.row(voronoi.selected()[position]);
+        assert!((diff.dot(&diff) - d2).abs() < 1e-6);
+
+        let mut manhattan = VoronoiDecomposer::new_with_metric(data.view().into(), 0, crate::metric::Manhattan);
+        manhattan.select(20);
+        let (position, d2) = manhattan.nearest_center(data.row(42)).unwrap();
+        assert_eq!(position, manhattan.assignments()[42]);
+        assert_eq!(d2, manhattan.distance2(42, manhattan.selected()[position]));
+
+        let error = voronoi.nearest_center(data.row(0).slice(ndarray::s![..3])).unwrap_err();
+        assert_eq!(error, VoronoiError::InvalidDimension { expected: 13, got: 3 });
+
+        let kernel = data.dot(&data.t());
+        let gram = VoronoiDecomposer::from_gram(kernel.view(), 0).unwrap();
+        assert_eq!(gram.nearest_center(data.row(0)).unwrap_err(), VoronoiError::UnsupportedQuery);
+    }
+
+    #[test]
+    fn fill_distance2() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(20);
+
+        // over the dataset itself, this is the largest radius
+        let expected = voronoi.cells().radius2.iter().copied().fold(0.0, f64::max);
+        let fill2 = voronoi.fill_distance2(data.view()).unwrap();
+        assert!((fill2 - expected).abs() < 1e-6 * expected);
+
+        // a regular grid over the range of the first two features
+        let points = data.slice(ndarray::s![.., ..2]);
+        let mut voronoi = VoronoiDecomposer::new(points.into(), 0);
+        voronoi.select(50);
+        let min = points.fold_axis(Axis(0), f64::INFINITY, |&a, &b| a.min(b));
+        let max = points.fold_axis(Axis(0), f64::NEG_INFINITY, |&a, &b| a.max(b));
+        let grid = Array2::from_shape_fn((40 * 40, 2), |(i, j)| {
+            let step = if j == 0 { i % 40 } else { i / 40 };
+            min[j] + (max[j] - min[j]) * step as f64 / 39.0
+        });
+        let fill2 = voronoi.fill_distance2(grid.view()).unwrap();
+        let brute_force = grid.rows().into_iter().map(|query| {
+            voronoi.selected().iter().map(|&center| {
+                let diff = &query - &points.row(center);
+                diff.dot(&diff)
+            }).fold(f64::INFINITY, f64::min)
+        }).fold(0.0, f64::max);
+        assert!((fill2 - brute_force).abs() < 1e-6 * brute_force);
+
+        assert_eq!(voronoi.fill_distance2(grid.slice(ndarray::s![..0, ..])).unwrap(), 0.0);
+        let error = voronoi.fill_distance2(data.view()).unwrap_err();
+        assert_eq!(error, VoronoiError::InvalidDimension { expected: 2, got: 13 });
+    }
+
+    #[test]
+    fn is_exhausted() {
+        // 6 points, with 2 duplicates
+        let points = ndarray::arr2(&[[0.0, 0.0], [1.0, 0.0], [0.0, 0.0], [2.0, 1.0], [1.0, 0.0], [3.0, 3.0]]);
+        let mut voronoi = VoronoiDecomposer::new(points.view().into(), 0);
+        assert!(!voronoi.is_exhausted());
+
+        assert_eq!(voronoi.select(10).len(), 3);
+        assert!(voronoi.is_exhausted());
+        assert_eq!(voronoi.next_candidate(), None);
+        assert_eq!(voronoi.iter_selections().next(), None);
+        assert_eq!(voronoi.len(), 4);
+    }
+
+    #[test]
+    fn cell_infos() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(20);
+
+        let mut infos = voronoi.cell_infos();
+        assert_eq!(infos.len(), 21);
+        let cells = voronoi.cells();
+        for (cell_idx, info) in infos.iter().enumerate() {
+            assert_eq!(info.center, cells.center_idx[cell_idx]);
+            assert_eq!(info.farthest, cells.farthest[cell_idx]);
+            assert_eq!(info.radius2, cells.radius2[cell_idx]);
+        }
+
+        infos.sort_by(|a, b| b.radius2.partial_cmp(&a.radius2).unwrap());
+        assert_eq!(infos[0].farthest, voronoi.next_point().0);
+    }
+
+    #[test]
+    fn smallest_cell() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(voronoi.smallest_cell(), (0, voronoi.initial_radius2()));
+
+        voronoi.select(30);
+        let (center, radius2) = voronoi.smallest_cell();
+        let cells = voronoi.cells();
+        let cell_idx = cells.center_idx.iter().position(|&c| c == center).unwrap();
+        assert_eq!(cells.radius2[cell_idx], radius2);
+        assert!(cells.radius2.iter().all(|&r| r >= radius2));
+    }
+
+    #[test]
+    fn radius() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(10);
+
+        let cells = voronoi.cells();
+        let radius = cells.radius();
+        assert_eq!(radius.len(), cells.len());
+        for (&r, &r2) in radius.iter().zip(cells.radius2) {
+            assert_eq!(r, r2.sqrt());
+        }
+    }
+
+    #[test]
+    fn min_pairwise_distance2() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(voronoi.selection_min_pairwise_distance2(), f64::INFINITY);
+
+        let distance2 = |i: usize, j: usize| {
+            let diff = &data.row(i) - &data.row(j);
+            diff.dot(&diff)
+        };
+        let check = |voronoi: &VoronoiDecomposer| {
+            let selected = voronoi.selected();
+            let mut expected = f64::INFINITY;
+            for (i, &a) in selected.iter().enumerate() {
+                for &b in &selected[i + 1..] {
+                    expected = expected.min(distance2(a, b));
+                }
+            }
+            let actual = voronoi.selection_min_pairwise_distance2();
+            assert!(f64::abs(actual - expected) < 1e-6 * expected, "{} != {}", actual, expected);
+        };
+
+        voronoi.select(30);
+        check(&voronoi);
+
+        // for FPS, this is the radius that triggered the last selection
+        let radius2 = voronoi.select_with_radii(1)[0].1;
+        assert!(f64::abs(voronoi.selection_min_pairwise_distance2() - radius2) < 1e-6 * radius2);
+
+        voronoi.remove_last();
+        voronoi.remove_last();
+        check(&voronoi);
+    }
+
+    #[test]
+    fn from_points() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::from_points(data.view().into());
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(voronoi.select(10), expected.select(10));
+
+        let mut voronoi = VoronoiDecomposer::try_from(data.view()).unwrap();
+        assert_eq!(voronoi.selected(), [0]);
+        assert_eq!(voronoi.select(10), expected.selected()[1..]);
+
+        let empty = Array2::<f64>::zeros((0, 3));
+        assert_eq!(VoronoiDecomposer::try_from(empty.view()).unwrap_err(), VoronoiError::EmptyDataset);
+    }
+
+    #[test]
+    fn last_recompute_count() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(voronoi.last_recompute_count(), 506);
+
+        voronoi.enable_trace();
+        for _ in 0..50 {
+            voronoi.select(1);
+            let event = voronoi.take_trace().pop().unwrap();
+            assert_eq!(event.n_distances, voronoi.len() - 1 + voronoi.last_recompute_count());
+        }
+
+        // the pruning skips most points once many points are selected
+        assert!(voronoi.last_recompute_count() < 506 / 2);
+    }
+
+    #[test]
+    fn distance2() {
+        let data = ndarray::arr2(&[[0.0, 0.0, 0.0], [0.5, 0.5, 0.5], [9.5, 9.5, 9.5]]);
+
+        let voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(voronoi.distance2(0, 1), 0.75);
+        assert_eq!(voronoi.distance2(1, 2), 243.0);
+
+        // the distances use periodic boundary conditions
+        let cell = [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]];
+        let periodic = VoronoiDecomposer::new_periodic(data.view().into(), 0, cell).unwrap();
+        assert!(f64::abs(periodic.distance2(1, 2) - 3.0) < 1e-12);
+        assert_eq!(voronoi.distance2(2, 2), 0.0);
+    }
+
+    #[test]
+    fn cell_centroids() {
+        let data = Array2::from_shape_vec((5, 2), vec![
+            0.0, 0.0,
+            0.0, 1.0,
+            10.0, 0.0,
+            10.0, 2.0,
+            12.0, 0.0,
+        ]).unwrap();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.add_point(4);
+
+        let centroids = voronoi.cell_centroids().unwrap();
+        assert_eq!(centroids, ndarray::arr2(&[[0.0, 0.5], [32.0 / 3.0, 2.0 / 3.0]]));
+
+        let kernel = data.dot(&data.t());
+        let gram = VoronoiDecomposer::from_gram(kernel.view(), 0).unwrap();
+        assert!(gram.cell_centroids().is_none());
+    }
+
+    #[test]
+    fn add_group() {
+        let data = boston();
+
+        let mut grouped = VoronoiDecomposer::new(data.view().into(), 0);
+        let mut single = VoronoiDecomposer::new(data.view().into(), 0);
+
+        let group = [12, 408, 99, 12, 250];
+        let max_radius2 = grouped.add_group(&group);
+        for &point in &group {
+            single.add_point(point);
+        }
+
+        assert_eq!(grouped.selected(), single.selected());
+        assert_eq!(grouped.cells().radius2, single.cells().radius2);
+        assert_eq!(max_radius2, single.next_point().1);
+    }
+
+    #[test]
+    fn selected_mask() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(10);
+
+        let mask = voronoi.selected_mask();
+        assert_eq!(mask.len(), 506);
+        assert_eq!(mask.iter().filter(|&&selected| selected).count(), 11);
+        for &point in voronoi.selected() {
+            assert!(mask[point]);
+        }
+    }
+
+    #[test]
+    fn extend_dataset() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.slice(ndarray::s![..300, ..]).into(), 0);
+        voronoi.select(20);
+        voronoi.extend_dataset(data.slice(ndarray::s![300.., ..])).unwrap();
+        assert_eq!(voronoi.assignments().len(), 506);
+
+        let mut expected = VoronoiDecomposer::new_multi(data.view().into(), voronoi.selected()).unwrap();
+        assert_eq!(voronoi.assignments(), expected.assignments());
+        assert_same_distances2(&voronoi.nearest_distances2(), &expected.nearest_distances2());
+        assert_eq!(voronoi.select(30), expected.select(30));
+
+        // errors leave the decomposer unchanged
+        let mut invalid = data.slice(ndarray::s![..3, ..]).to_owned();
+        invalid[[1, 4]] = f64::NAN;
+        let error = voronoi.extend_dataset(invalid.view()).unwrap_err();
+        assert_eq!(error, VoronoiError::NaNDistance { index: 507 });
+        assert_eq!(voronoi.assignments().len(), 506);
+        assert_eq!(voronoi.select(10), expected.select(10));
+
+        let error = voronoi.extend_dataset(Array2::zeros((3, 2)).view()).unwrap_err();
+        assert_eq!(error, VoronoiError::InvalidDimension { expected: 13, got: 2 });
+
+        let kernel = data.dot(&data.t());
+        let mut gram = VoronoiDecomposer::from_gram(kernel.view(), 0).unwrap();
+        assert_eq!(gram.extend_dataset(data.view()).unwrap_err(), VoronoiError::CanNotExtend);
+    }
+
+    #[test]
+    fn iter_selections() {
+        let data = boston();
+
+        let mut expected = VoronoiDecomposer::new(data.view().into(), 0);
+        let expected = expected.select_with_radii(20);
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let selected = voronoi.iter_selections().take(20).collect::<Vec<_>>();
+        assert_eq!(selected, expected);
+        assert_eq!(voronoi.len(), 21);
+
+        // the iterator stops once all points are selected
+        let remaining = voronoi.iter_selections().count();
+        assert_eq!(remaining, 506 - 21);
+        assert_eq!(voronoi.iter_selections().next(), None);
+    }
+
+    #[test]
+    fn new_multi() {
+        let data = boston();
+
+        let anchors = [42, 3, 300];
+        let voronoi = VoronoiDecomposer::new_multi(data.view().into(), &anchors).unwrap();
+        assert_eq!(voronoi.selected(), anchors);
+
+        // the next point is the farthest from all anchors
+        let (next, radius2) = voronoi.next_candidate().unwrap();
+        let distance2 = |i: usize, j: usize| Euclidean.distance2(data.row(i), data.row(j));
+        let nearest = |i: usize| anchors.iter().map(|&a| distance2(i, a)).fold(f64::INFINITY, f64::min);
+        assert!((nearest(next) - radius2).abs() < 1e-9 * radius2);
+        for point in 0..data.nrows() {
+            assert!(nearest(point) <= radius2 * (1.0 + 1e-9));
+        }
+
+        let expected = crate::fps_bruteforce(data.view(), 42, 3)[1..].to_vec();
+        let mut single = VoronoiDecomposer::new_multi(data.view().into(), &[42]).unwrap();
+        assert_eq!(single.select(2), expected);
+
+        let error = VoronoiDecomposer::new_multi(data.view().into(), &[]).err();
+        assert_eq!(error, Some(VoronoiError::EmptySelection));
+        let error = VoronoiDecomposer::new_multi(data.view().into(), &[1, 506]).err();
+        assert_eq!(error, Some(VoronoiError::IndexOutOfBounds { index: 506, n_points: 506 }));
+
+        // the distance between the second and third point overflows
+        let huge = ndarray::arr2(&[[0.0], [8e153], [-8e153]]);
+        let error = VoronoiDecomposer::new_multi(huge.view().into(), &[0, 1, 2]).err();
+        assert_eq!(error, Some(VoronoiError::NonFiniteDistance { i: 1, j: 2 }));
+        let error = VoronoiDecomposer::new_from_selection(huge.view().into(), &[0, 1, 2]).err();
+        assert_eq!(error, Some(VoronoiError::NonFiniteDistance { i: 1, j: 2 }));
+
+        let huge = ndarray::arr2(&[[0.0], [1e154], [-1e154]]);
+        assert!(VoronoiDecomposer::new_multi(huge.view().into(), &[0, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn new_from_selection() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let first = voronoi.select(30);
+        let second = voronoi.select(30);
+
+        let mut selected = vec![0];
+        selected.extend(&first);
+        let mut resumed = VoronoiDecomposer::new_from_selection(data.view().into(), &selected).unwrap();
+        assert_eq!(resumed.select(30), second);
+
+        let error = VoronoiDecomposer::new_from_selection(data.view().into(), &[]).unwrap_err();
+        assert_eq!(error, VoronoiError::EmptySelection);
+
+        let error = VoronoiDecomposer::new_from_selection(data.view().into(), &[3, 600]).unwrap_err();
+        assert_eq!(error, VoronoiError::IndexOutOfBounds { index: 600, n_points: 506 });
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn save_load() {
+        let data = boston();
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(30);
+
+        let mut path = std::env::temp_dir();
+        path.push(format!("farthest-sampler-{}-state.bin", std::process::id()));
+        voronoi.save(&path).unwrap();
+
+        let mut loaded = VoronoiDecomposer::load(&path, data.view().into()).unwrap();
+        assert_eq!(loaded.selected(), voronoi.selected());
+        assert_eq!(loaded.cells().radius2, voronoi.cells().radius2);
+        assert_eq!(loaded.initial_radius2(), voronoi.initial_radius2());
+        assert_eq!(loaded.select(30), voronoi.select(30));
+
+        let error = VoronoiDecomposer::load(&path, data.slice(ndarray::s![..100, ..]).into()).unwrap_err();
+        assert_eq!(error.to_string(), "the saved state was created with 506 points, got 100 points");
+
+        let weights = Array1::from_elem(506, 2.0);
+        let weighted = VoronoiDecomposer::new_weighted(data.view().into(), weights.view(), 0).unwrap();
+        let error = weighted.save(&path).unwrap_err();
+        assert_eq!(error.to_string(), "only decomposers created with VoronoiDecomposer::new can be saved");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn invalid_state() {
+        let data = boston();
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(10);
+        let state: VoronoiState<f64> = bincode::deserialize(&bincode::serialize(&voronoi).unwrap()).unwrap();
+
+        let restore = |update: &dyn Fn(&mut VoronoiState<f64>)| {
+            let mut state = state.clone();
+            update(&mut state);
+            VoronoiDecomposer::from_state(data.view().into(), state).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            restore(&|state| state.kind = "cosine".into()),
+            "invalid saved state: unsupported decomposer kind 'cosine'"
+        );
+        assert_eq!(
+            restore(&|state| { state.cells.radius2.pop(); }),
+            "invalid saved state: the cells arrays have different lengths"
+        );
+        assert_eq!(
+            restore(&|state| { state.cells.points[0].pop(); }),
+            "invalid saved state: a point is not in any cell"
+        );
+        assert_eq!(
+            restore(&|state| { let point = state.cells.points[0][0]; state.cells.points[1].push(point); }),
+            "invalid saved state: a point is in multiple cells"
+        );
+        assert_eq!(
+            restore(&|state| state.cells.center_idx.swap(0, 1)),
+            "invalid saved state: the center or farthest point of a cell is not in this cell"
+        );
+
+        // the state is valid without modifications
+        let restored = VoronoiDecomposer::from_state(data.view().into(), state).unwrap();
+        assert_eq!(restored.selected(), voronoi.selected());
+    }
+
+    /// Deterministic pseudo-random points in `[-1, 1)`
+    pub(crate) fn random_points(n_points: usize, n_features: usize, seed: u64) -> Array2<f64> {
+        let mut state = seed;
+        Array2::from_shape_fn((n_points, n_features), |_| {
+            // linear congruential generator from Knuth's MMIX
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+        })
+    }
+
+    #[test]
+    fn farthest_invariant() {
+        let data = random_points(300, 4, 12);
+        let check = |voronoi: &VoronoiDecomposer| {
+            let cells = voronoi.cells();
+            for i in 0..cells.len() {
+                let diff = &data.row(cells.center_idx[i]) - &data.row(cells.farthest[i]);
+                assert!(cells.points[i].contains(&cells.farthest[i]));
+                assert!(f64::abs(diff.dot(&diff) - cells.radius2[i]) < 1e-12);
+                for &point in &cells.points[i] {
+                    assert_eq!(voronoi.assignments()[point], i);
+                    assert!(voronoi.nearest_distances2()[point] <= cells.radius2[i]);
+                }
+            }
+        };
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        check(&voronoi);
+        for step in 0..60 {
+            match step % 4 {
+                0 => voronoi.add_point((step * 37 + 11) % 300),
+                1 => voronoi.add_points((0..5).map(|i| (step * 53 + i * 17) % 300)),
+                2 => { voronoi.select(3); }
+                _ => { voronoi.remove_last(); }
+            }
+            check(&voronoi);
+        }
+    }
+
+    #[test]
+    fn same_as_bruteforce() {
+        for seed in 0..30 {
+            let n_points = 50 + (seed as usize * 37) % 250;
+            let n_features = 1 + seed as usize % 10;
+            let data = random_points(n_points, n_features, seed);
+
+            let initial = (seed as usize * 13) % n_points;
+            let n_select = n_points / 2;
+            let expected = crate::fps_bruteforce(data.view(), initial, n_select);
+            assert_eq!(select_fps(data.view(), n_select, initial), expected, "seed = {}", seed);
+
+            // duplicate some of the points, and select all of them
+            let duplicated = ndarray::concatenate![ndarray::Axis(0), data, data.slice(ndarray::s![..n_points / 3, ..])];
+            let n_select = duplicated.nrows();
+            let expected = crate::fps_bruteforce(duplicated.view(), initial, n_select);
+            assert_eq!(expected.len(), n_points);
+            assert_eq!(select_fps(duplicated.view(), n_select, initial), expected, "seed = {}", seed);
+        }
+    }
+
+    #[test]
+    fn cosine() {
+        let data = random_points(50, 5, 0xdead_beef);
+
+        // brute force FPS with the cosine distance
+        let cosine = |i: usize, j: usize| {
+            let (a, b) = (data.row(i), data.row(j));
+            2.0 * (1.0 - a.dot(&b) / (a.dot(&a) * b.dot(&b)).sqrt())
+        };
+        let mut expected = vec![0];
+        let mut min_distances = (0..50).map(|j| cosine(0, j)).collect::<Vec<_>>();
+        for _ in 0..20 {
+            let (new, _) = find_max(min_distances.iter());
+            expected.push(new);
+            for (j, d) in min_distances.iter_mut().enumerate() {
+                *d = f64::min(*d, cosine(new, j));
+            }
+        }
+
+        let mut voronoi = VoronoiDecomposer::new_cosine(data.view().into(), 0).unwrap();
+        voronoi.select(20);
+        assert_eq!(voronoi.selected(), expected);
+
+        let mut voronoi = VoronoiDecomposer::new_with_metric(data.view().into(), 0, Cosine);
+        voronoi.select(20);
+        assert_eq!(voronoi.selected(), expected);
+
+        let mut zero = data.clone();
+        zero.row_mut(3).fill(0.0);
+        let error = VoronoiDecomposer::new_cosine(zero.view().into(), 0).unwrap_err();
+        assert_eq!(error, VoronoiError::ZeroVector { index: 3 });
+    }
+
+    #[test]
+    fn add_points() {
+        let data = boston();
+
+        let new_points = [42, 3, 133, 8, 3, 250, 0];
+
+        let mut sequential = VoronoiDecomposer::new(data.view().into(), 0);
+        for &point in &new_points {
+            if !sequential.selected().contains(&point) {
+                sequential.add_point(point);
+            }
+        }
+
+        let mut batch = VoronoiDecomposer::new(data.view().into(), 0);
+        batch.add_points(new_points.iter().copied());
+        assert_eq!(batch.assignments(), sequential.assignments());
+
+        assert_eq!(batch.selected(), [0, 42, 3, 133, 8, 250]);
+        assert_eq!(batch.selected(), sequential.selected());
+        assert_eq!(batch.cells().radius2, sequential.cells().radius2);
+        assert_eq!(batch.cells().farthest, sequential.cells().farthest);
+    }
+
+    #[test]
+    fn periodic() {
+        let data = Array2::from_shape_vec((3, 3), vec![
+            0.5, 5.0, 5.0,
+            9.5, 5.0, 5.0,
+            5.0, 5.0, 5.0,
+        ]).unwrap();
+        let cell = [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]];
+
+        let mut voronoi = VoronoiDecomposer::new_periodic(data.view().into(), 0, cell).unwrap();
+        let selected = voronoi.select_with_radii(2);
+        assert_eq!(selected[0].0, 2);
+        assert!((selected[0].1 - 4.5 * 4.5).abs() < 1e-12);
+        assert_eq!(selected[1].0, 1);
+        assert!((selected[1].1 - 1.0).abs() < 1e-12);
+
+        let data = Array2::from_elem((3, 2), 0.0);
+        let error = VoronoiDecomposer::new_periodic(data.view().into(), 0, cell).unwrap_err();
+        assert_eq!(error, VoronoiError::InvalidDimension { expected: 3, got: 2 });
+
+        let data = Array2::from_elem((3, 3), 0.0);
+        let cell = [[1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+        let error = VoronoiDecomposer::new_periodic(data.view().into(), 0, cell).unwrap_err();
+        assert_eq!(error, VoronoiError::SingularCell);
+    }
+
+    #[test]
+    fn metrics() {
+        let data = Array2::from_shape_vec((4, 2), vec![
+            0.0, 1.0,
+            0.8, 0.5,
+            0.0, 0.0,
+            1.0, 0.0,
+        ]).unwrap();
+
+        let mut voronoi = VoronoiDecomposer::new_with_metric(data.view().into(), 0, Manhattan);
+        let selected = voronoi.select_with_radii(3);
+        let expected: [(usize, f64); 3] = [(3, 4.0), (2, 1.0), (1, 0.49)];
+        for (&(point, radius2), &(expected_point, expected_radius2)) in selected.iter().zip(&expected) {
+            assert_eq!(point, expected_point);
+            assert!((radius2 - expected_radius2).abs() < 1e-12);
+        }
+
+        let data = boston();
+
+        let mut euclidean = VoronoiDecomposer::new_with_metric(data.view().into(), 0, Euclidean);
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(euclidean.select(100), voronoi.select(100));
+    }
+
+    #[test]
+    fn gram() {
+        let data = boston();
+
+        // the linear kernel gives back the Euclidean distance
+        let kernel = data.dot(&data.t());
+        let mut gram = VoronoiDecomposer::from_gram(kernel.view(), 0).unwrap();
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        assert_eq!(gram.select(100), voronoi.select(100));
+
+        let error = VoronoiDecomposer::from_gram(data.view(), 0).unwrap_err();
+        assert_eq!(error, VoronoiError::NotSquare { rows: 506, columns: 13 });
+
+        let mut kernel = Array2::from_elem((3, 3), 1.0);
+        kernel[[0, 2]] = 0.5;
+        let error = VoronoiDecomposer::from_gram(kernel.view(), 0).unwrap_err();
+        assert_eq!(error, VoronoiError::NotSymmetric { i: 0, j: 2 });
+    }
+
+    #[test]
+    fn sparse() {
+        // one-hot points, with a few additional values
+        let mut dense = Array2::<f64>::zeros((200, 50));
+        for i in 0..200 {
+            dense[[i, i % 50]] = 1.0;
+            dense[[i, (i * 7) % 50]] += 0.5 * (i % 3) as f64;
+        }
+
+        let mut indptr = vec![0];
+        let mut indices = Vec::new();
+        let mut data = Vec::new();
+        for row in dense.rows() {
+            for (column, &value) in row.iter().enumerate() {
+                if value != 0.0 {
+                    indices.push(column);
+                    data.push(value);
+                }
+            }
+            indptr.push(indices.len());
+        }
+
+        let mut sparse = VoronoiDecomposer::from_sparse(50, &indptr, &indices, &data, 0).unwrap();
+        let mut voronoi = VoronoiDecomposer::new(dense.view().into(), 0);
+        assert_eq!(sparse.select(60), voronoi.select(60));
+        assert_eq!(sparse.cells().radius2, voronoi.cells().radius2);
+        assert_eq!(sparse.nearest_center(dense.row(17)).unwrap().0, voronoi.nearest_center(dense.row(17)).unwrap().0);
+
+        let error = VoronoiDecomposer::from_sparse(10, &indptr, &indices, &data, 0).unwrap_err();
+        assert!(matches!(error, VoronoiError::InvalidSparseMatrix { .. }));
+
+        let error = VoronoiDecomposer::from_sparse(50, &indptr[1..], &indices, &data, 0).unwrap_err();
+        assert!(matches!(error, VoronoiError::InvalidSparseMatrix { .. }));
+
+        let unsorted = [2, 1];
+        let error = VoronoiDecomposer::from_sparse(3, &[0, 2], &unsorted, &[1.0, 1.0], 0).unwrap_err();
+        assert!(matches!(error, VoronoiError::InvalidSparseMatrix { .. }));
+    }
+
+    #[test]
+    #[cfg(feature = "half")]
+    fn half() {
+        use half::bf16;
+
+        let data = random_points(5000, 32, 3).mapv(|v| bf16::from_f64(10.0 * v));
+        let converted = data.mapv(f32::from);
+
+        let mut half = VoronoiDecomposer::new_half(data.view().into(), 0).unwrap();
+        let mut voronoi = VoronoiDecomposer::new(converted.view().into(), 0);
+        assert_eq!(half.select(200), voronoi.select(200));
+
+        for (&a, &b) in half.cells().radius2.iter().zip(voronoi.cells().radius2) {
+            assert!((a - b).abs() <= 1e-4 * b, "{} vs {}", a, b);
+        }
+
+        assert_eq!(half.nearest_center(converted.row(17)).unwrap().0, half.assignments()[17]);
+
+        let error = half.extend_dataset(converted.view()).unwrap_err();
+        assert_eq!(error, VoronoiError::CanNotExtend);
+    }
+
+    #[test]
+    fn batched_distances() {
+        let data = boston();
+
+        // this uses the copy of the centers (and BLAS with the `blas`
+        // feature), and should match the distances computed one at a time
+        let mut distances = EuclideanDistances::new(data.view().into());
+        distances.push_center(3);
+        distances.push_center(42);
+        let points = (0..data.nrows()).rev().step_by(3).collect::<Vec<_>>();
+        let mut batched = vec![-1.0];
+        distances.center_distances2(1, 42, &points, &mut batched);
+
+        assert_eq!(batched.len(), points.len() + 1);
+        assert_eq!(batched[0], -1.0);
+        for (&point, &d2) in points.iter().zip(&batched[1..]) {
+            let expected = distances.distance2(42, point);
+            assert!(f64::abs(d2 - expected) <= 1e-10 * expected.max(1.0));
+        }
+
+        let mut between = Vec::new();
+        distances.distances2_between_centers(1, 42, &[3], &mut between);
+        assert_eq!(between, [distances.distance2(42, 3)]);
+
+        distances.pop_center();
+        distances.push_center(7);
+        let mut batched = Vec::new();
+        distances.center_distances2(1, 7, &points, &mut batched);
+        assert_eq!(batched[0], distances.distance2(7, points[0]));
+    }
+
+    #[test]
+    fn transposed() {
+        let data = boston();
+        let transposed = data.t().as_standard_layout().into_owned();
+
+        // a column-major view gives the same selection as the original data
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        let mut strided = VoronoiDecomposer::new(transposed.t().into(), 0);
+        assert_eq!(voronoi.select(50), strided.select(50));
+    }
+
+    #[test]
+    fn check_boston_f32() {
+        let data = boston();
+        let data = data.mapv(|v| v as f32);
+
+        let expected = vec![9, 3, 11, 6, 1, 10, 8, 0, 12, 2, 5, 7, 4];
+        let mut voronoi = VoronoiDecomposer::new(data.t().into(), expected[0]);
+        voronoi.select(12);
+        assert_eq!(voronoi.selected(), expected);
+
+        let radius2: &[f32] = voronoi.cells().radius2;
+        assert_eq!(radius2.len(), 13);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn thread_count_independence() {
+        let data = boston();
+
+        let run = |n_threads| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(n_threads).build().unwrap();
+            pool.install(|| {
+                let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+                voronoi.select(200);
+                (voronoi.selected().to_vec(), voronoi.cells().radius2.to_vec())
+            })
+        };
+
+        let (serial_selected, serial_radius2) = run(1);
+        let (parallel_selected, parallel_radius2) = run(4);
+        assert_eq!(serial_selected, parallel_selected);
+        assert_eq!(serial_radius2, parallel_radius2);
+    }
+
+    #[test]
+    fn ties() {
+        // points on a regular grid, with a lot of equal distances
+        let data = Array2::from_shape_fn((100, 2), |(i, j)| if j == 0 { (i / 10) as f64 } else { (i % 10) as f64 });
+
+        let expected = crate::fps_bruteforce(data.view(), 0, 60);
+        assert_eq!(&expected[..5], [0, 99, 9, 90, 44]);
+
+        let mut voronoi = VoronoiDecomposer::new(data.view().into(), 0);
+        voronoi.select(59);
+        assert_eq!(voronoi.selected(), expected);
+
+        #[cfg(feature = "rayon")]
+        for &n_threads in &[1, 2, 4] {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(n_threads).build().unwrap();
+            let selected = pool.install(|| select_fps(data.view(), 60, 0));
+            assert_eq!(selected, expected);
+        }
+    }
+
+    #[test]
+    fn check_boston() {
+        let data = boston();
 
         let expected = vec![9, 3, 11, 6, 1, 10, 8, 0, 12, 2, 5, 7, 4];
         for n_select in 1..13 {
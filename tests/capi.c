@@ -0,0 +1,61 @@
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "farthest_sampler.h"
+
+#define CHECK(condition) do {                                       \
+    if (!(condition)) {                                             \
+        fprintf(stderr, "%s:%d: check failed: %s\n",                \
+                __FILE__, __LINE__, #condition);                    \
+        return 1;                                                   \
+    }                                                               \
+} while (0)
+
+#define N_ROWS 100
+#define N_COLS 3
+
+int main(void) {
+    double points[N_ROWS * N_COLS];
+    for (size_t i = 0; i < N_ROWS; i++) {
+        points[i * N_COLS + 0] = (double)(i % 10);
+        points[i * N_COLS + 1] = (double)(i / 10);
+        points[i * N_COLS + 2] = (double)((i * 7) % 13);
+    }
+
+    CHECK(vfps_new(NULL, N_ROWS, N_COLS, 0) == NULL);
+    CHECK(vfps_new(points, N_ROWS, N_COLS, N_ROWS) == NULL);
+    CHECK(vfps_new(points, 0, N_COLS, 0) == NULL);
+    CHECK(vfps_new(points, (size_t)-1, N_COLS, 0) == NULL);
+
+    double invalid[2 * N_COLS] = {0.0, 0.0, 0.0, 0.0, NAN, 0.0};
+    CHECK(vfps_new(invalid, 2, N_COLS, 0) == NULL);
+
+    vfps_decomposer* decomposer = vfps_new(points, N_ROWS, N_COLS, 0);
+    CHECK(decomposer != NULL);
+
+    CHECK(vfps_add_point(decomposer, 99) == 0);
+    CHECK(vfps_add_point(decomposer, 99) == -1);
+    CHECK(vfps_add_point(decomposer, N_ROWS) == -1);
+
+    size_t n_selected = 0;
+    size_t* selected = vfps_select(decomposer, 20, &n_selected);
+    CHECK(selected != NULL);
+    CHECK(n_selected == 20);
+    for (size_t i = 0; i < n_selected; i++) {
+        CHECK(selected[i] < N_ROWS);
+        CHECK(selected[i] != 0 && selected[i] != 99);
+    }
+    vfps_free_indices(selected, n_selected);
+
+    size_t n_cells = 0;
+    const double* radii = vfps_radii(decomposer, &n_cells);
+    CHECK(radii != NULL);
+    CHECK(n_cells == 22);
+    for (size_t i = 0; i < n_cells; i++) {
+        CHECK(radii[i] >= 0.0);
+    }
+
+    vfps_free(decomposer);
+    return 0;
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "capi")]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Compile and run `tests/capi.c` against the shared library
+#[test]
+fn c_round_trip() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // the test executable and the shared library are both in target/<profile>/deps
+    let deps = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let executable = deps.join("capi-c-test");
+
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".into()))
+        .arg(root.join("tests").join("capi.c"))
+        .arg("-I").arg(root.join("include"))
+        .arg("-L").arg(&deps)
+        .arg(format!("-Wl,-rpath,{}", deps.display()))
+        .arg("-lfarthest_sampler")
+        .arg("-o").arg(&executable)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success());
+
+    // cargo puts target/<profile> in the library path, which can contain a
+    // shared library built with a different set of features
+    let status = Command::new(&executable)
+        .env("LD_LIBRARY_PATH", &deps)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
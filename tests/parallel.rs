@@ -0,0 +1,69 @@
+#![cfg(feature = "rayon")]
+
+use ndarray::Array2;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use farthest_sampler::VoronoiDecomposer;
+
+/// Points on a regular grid (with a lot of ties between distances), followed
+/// by random points
+fn dataset() -> Array2<f64> {
+    let grid = 30;
+    let n_random = 5000;
+    let mut rng = StdRng::seed_from_u64(12);
+
+    let mut points = Array2::zeros((grid * grid * grid + n_random, 3));
+    for (i, mut point) in points.rows_mut().into_iter().enumerate() {
+        if i < grid * grid * grid {
+            point[0] = (i % grid) as f64;
+            point[1] = ((i / grid) % grid) as f64;
+            point[2] = (i / (grid * grid)) as f64;
+        } else {
+            for value in &mut point {
+                *value = rng.gen_range(-5.0..35.0);
+            }
+        }
+    }
+    points
+}
+
+/// Select 500 points with the given number of threads, and return the
+/// selected points and the bit representation of the radius
+fn select(points: &Array2<f64>, n_threads: usize) -> Vec<(usize, u64)> {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(n_threads).build().unwrap();
+    pool.install(|| {
+        let mut voronoi = VoronoiDecomposer::new(points.view().into(), 0);
+        voronoi.select_with_radii(500).into_iter()
+            .map(|(point, radius2)| (point, radius2.to_bits()))
+            .collect()
+    })
+}
+
+#[test]
+fn independent_of_thread_count() {
+    let points = dataset();
+    let reference = select(&points, 1);
+    assert_eq!(reference.len(), 500);
+
+    for &n_threads in &[2, 4, 8] {
+        assert_eq!(select(&points, n_threads), reference, "different selection with {} threads", n_threads);
+    }
+}
+
+#[test]
+fn construction_independent_of_thread_count() {
+    let points = dataset();
+    let build = |n_threads: usize| {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(n_threads).build().unwrap();
+        pool.install(|| {
+            let voronoi = VoronoiDecomposer::new(points.view().into(), 42);
+            (voronoi.snapshot_cells(), voronoi.coverage(), voronoi.initial_radius2().to_bits())
+        })
+    };
+
+    let reference = build(1);
+    for &n_threads in &[2, 4, 8] {
+        assert_eq!(build(n_threads), reference, "different initial state with {} threads", n_threads);
+    }
+}